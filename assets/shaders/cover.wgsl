@@ -0,0 +1,37 @@
+#import bevy_sprite::{mesh2d_vertex_output::VertexOutput, mesh2d_view_bindings::globals}
+
+// Note: This is a vec4 because on WebGPU it needs to have a size of 16
+@group(2) @binding(0) var<uniform> grid_size: vec4<f32>;
+@group(2) @binding(1) var tileset_texture: texture_2d_array<f32>;
+@group(2) @binding(2) var tileset_sampler: sampler;
+@group(2) @binding(3) var tilemap_texture: texture_2d<u32>;
+
+// These match cover.rs
+const COVERED: u32 = 0u;
+const REVEALED: u32 = 255u;
+const TICKS_PER_SECOND: f32 = 60.0;
+const STAMPS: u32 = 254u;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let size = grid_size.xy;
+    let tilemap_uv = mesh.uv * size;
+    let tile_uv_fract = fract(mesh.uv * size);
+    let tile_uv = vec2<f32>(tile_uv_fract.x, 1.0 - tile_uv_fract.y);
+    let value = textureLoad(tilemap_texture, vec2<u32>(tilemap_uv), 0).x;
+
+    // The tiles go from covered to revealed
+    let last = textureNumLayers(tileset_texture) - 1u;
+    var tile_index: u32;
+    if value == COVERED {
+        tile_index = 0u;
+    } else if value == REVEALED {
+        tile_index = last;
+    } else {
+        // Cells still fading in store the tick they were revealed at
+        let now = u32(globals.time * TICKS_PER_SECOND) % STAMPS;
+        let age = (now + STAMPS - (value - 1u)) % STAMPS;
+        tile_index = min(age, last);
+    }
+    return textureSample(tileset_texture, tileset_sampler, tile_uv, tile_index);
+}
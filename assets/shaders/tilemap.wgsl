@@ -5,13 +5,36 @@
 @group(2) @binding(1) var tileset_texture: texture_2d_array<f32>;
 @group(2) @binding(2) var tileset_sampler: sampler;
 @group(2) @binding(3) var tilemap_texture: texture_2d<u32>;
+@group(2) @binding(4) var<uniform> palette: array<vec4<f32>, 16>;
+
+// The tile flags, these match `Tile` in tilemap.rs
+const FLIP_X: u32 = 1u;
+const FLIP_Y: u32 = 2u;
+const ROTATE: u32 = 4u;
+const PALETTE_SHIFT: u32 = 8u;
+const PALETTE_MASK: u32 = 0xfu;
 
 @fragment
 fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
     let size = grid_size.xy;
     let tilemap_uv = mesh.uv * size;
-    let tile_uv_fract = fract(mesh.uv * size);
-    let tile_uv = vec2<f32>(tile_uv_fract.x, 1.0 - tile_uv_fract.y);
-    let tile_index = textureLoad(tilemap_texture, vec2<u32>(tilemap_uv), 0).x;
-    return textureSample(tileset_texture, tileset_sampler, tile_uv, tile_index);
+    let tile = textureLoad(tilemap_texture, vec2<u32>(tilemap_uv), 0);
+    let tile_index = tile.x;
+    let flags = tile.y;
+
+    // The position within the tile with y pointing up
+    var uv = fract(mesh.uv * size);
+    if (flags & ROTATE) != 0u {
+        uv = vec2<f32>(1.0 - uv.y, uv.x);
+    }
+    if (flags & FLIP_X) != 0u {
+        uv.x = 1.0 - uv.x;
+    }
+    if (flags & FLIP_Y) != 0u {
+        uv.y = 1.0 - uv.y;
+    }
+
+    let tile_uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    let color = textureSample(tileset_texture, tileset_sampler, tile_uv, tile_index);
+    return color * palette[(flags >> PALETTE_SHIFT) & PALETTE_MASK];
 }
@@ -0,0 +1,97 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use maze::disjoint_set::{DisjointSet, UndoableDisjointSet};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+const SIZE: usize = 1000;
+
+/// All edges of a `SIZE`x`SIZE` grid in random order
+fn edges() -> Vec<(usize, usize)> {
+    let mut edges = Vec::with_capacity(2 * SIZE * SIZE);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let i = y * SIZE + x;
+            if x + 1 < SIZE {
+                edges.push((i, i + 1));
+            }
+            if y + 1 < SIZE {
+                edges.push((i, i + SIZE));
+            }
+        }
+    }
+    edges.shuffle(&mut StdRng::seed_from_u64(0));
+    edges
+}
+
+fn joined(edges: &[(usize, usize)]) -> DisjointSet {
+    let mut ds = DisjointSet::new(SIZE * SIZE);
+    for &(a, b) in edges {
+        if ds.find(a) != ds.find(b) {
+            ds.join(a, b);
+        }
+    }
+    ds
+}
+
+fn bench(c: &mut Criterion) {
+    let edges = edges();
+
+    let mut group = c.benchmark_group("disjoint_set_1M");
+    group.sample_size(10);
+
+    group.bench_function("join_all_edges", |b| b.iter(|| joined(&edges)));
+
+    group.bench_function("find_all", |b| {
+        b.iter_batched(
+            || joined(&edges),
+            |ds| ds.values().filter(|&root| root == 0).count(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    let ds = joined(&edges);
+    ds.values().for_each(drop);
+    group.bench_function("find_all_compressed", |b| {
+        b.iter(|| ds.values().filter(|&root| root == 0).count())
+    });
+
+    group.bench_function("join_all_edges_undoable", |b| {
+        b.iter(|| {
+            let mut ds = UndoableDisjointSet::new(SIZE * SIZE);
+            for &(a, b) in &edges {
+                ds.join(a, b);
+            }
+            ds
+        })
+    });
+
+    group.bench_function("rollback_all", |b| {
+        b.iter_batched(
+            || {
+                let mut ds = UndoableDisjointSet::new(SIZE * SIZE);
+                let checkpoint = ds.checkpoint();
+                for &(a, b) in &edges {
+                    ds.join(a, b);
+                }
+                (ds, checkpoint)
+            },
+            |(mut ds, checkpoint)| {
+                ds.rollback(checkpoint);
+                ds
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+
+    let ds = joined(&edges);
+    let max_depth = (0..ds.len()).map(|i| ds.depth(i)).max().unwrap();
+    println!(
+        "Max depth after joining {} elements: {}",
+        ds.len(),
+        max_depth
+    );
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
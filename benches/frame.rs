@@ -0,0 +1,80 @@
+//! The work done every frame for a maze of a given size
+
+use bevy::math::{URect, UVec2};
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::Image,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use maze::{
+    cover,
+    maze::{self as generator, MazeType},
+    tilemap,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+const SIZES: [usize; 3] = [16, 256, 2048];
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+
+    for size in SIZES {
+        let mut rng = StdRng::seed_from_u64(0);
+        let grid = generator::generate_grid(MazeType::Kruskal, size, size, &mut rng);
+
+        // Once every cell is revealed the cover only compares tiles
+        let mut cover = cover::Cover::new(size as u32, size as u32, 5);
+        cover.reveal(&grid, 0.0);
+        cover.finish_fades(1.0);
+        group.bench_function(BenchmarkId::new("update_cover", size), |b| {
+            b.iter(|| cover.reveal(&grid, 2.0))
+        });
+
+        let mut data = vec![0u8; size * size];
+
+        // Encoding a whole tilemap into a texture
+        let mut image = Image::new(
+            Extent3d {
+                width: size as u32,
+                height: size as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![0; size * size * tilemap::BYTES_PER_TILE],
+            TextureFormat::Rg16Uint,
+            RenderAssetUsages::all(),
+        );
+        let full = URect::new(0, 0, size as u32, size as u32);
+        group.bench_function(BenchmarkId::new("tilemap_upload", size), |b| {
+            b.iter(|| tilemap::copy_chunk(&data, size, full, &mut image.data))
+        });
+
+        // Finding and copying the one chunk which changed
+        let rects = tilemap::chunk_rects(UVec2::splat(size as u32));
+        let mut chunks: Vec<Vec<u8>> = rects
+            .iter()
+            .map(|rect| {
+                let mut chunk =
+                    vec![0; (rect.width() * rect.height()) as usize * tilemap::BYTES_PER_TILE];
+                tilemap::copy_chunk(&data, size, *rect, &mut chunk);
+                chunk
+            })
+            .collect();
+        group.bench_function(BenchmarkId::new("chunk_upload", size), |b| {
+            b.iter(|| {
+                data[size * size / 2] ^= 1;
+                for (rect, chunk) in rects.iter().zip(&mut chunks) {
+                    if tilemap::chunk_differs(&data, size, *rect, chunk) {
+                        tilemap::copy_chunk(&data, size, *rect, chunk);
+                    }
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
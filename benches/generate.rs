@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use maze::maze::{self as generator, MazeType};
+use rand::{rngs::StdRng, SeedableRng};
+
+const SIZES: [usize; 3] = [16, 256, 2048];
+
+/// Wfc scans the whole grid on every step, so it is quadratic in the number of
+/// cells and only the smallest size finishes in a reasonable time
+fn sizes(maze_type: MazeType) -> &'static [usize] {
+    match maze_type {
+        MazeType::Wfc => &SIZES[..1],
+        _ => &SIZES,
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    group.sample_size(10);
+
+    for maze_type in MazeType::ALL {
+        for &size in sizes(maze_type) {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", maze_type), size),
+                &size,
+                |b, &size| {
+                    let mut rng = StdRng::seed_from_u64(0);
+                    b.iter(|| generator::generate_grid(maze_type, size, size, &mut rng))
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
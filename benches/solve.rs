@@ -0,0 +1,32 @@
+use bevy::math::IVec2;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use maze::{
+    maze::{self as generator, MazeType},
+    solver,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+const SIZES: [usize; 3] = [16, 256, 2048];
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let mut rng = StdRng::seed_from_u64(0);
+        let grid = generator::generate_grid(MazeType::Backtracking, size, size, &mut rng);
+        let goal = IVec2::splat(size as i32 - 1);
+
+        group.bench_with_input(BenchmarkId::new("solve", size), &grid, |b, grid| {
+            b.iter(|| solver::solve(grid, IVec2::ZERO, goal))
+        });
+        group.bench_with_input(BenchmarkId::new("distances", size), &grid, |b, grid| {
+            b.iter(|| solver::distances(grid, IVec2::ZERO))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
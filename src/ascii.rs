@@ -0,0 +1,316 @@
+//! Conversion between grids and ASCII art of the form
+//!
+//! ```text
+//! +--+--+
+//! |     |
+//! +  +--+
+//! |  |  |
+//! +--+--+
+//! ```
+//!
+//! The first line is the northern edge of the maze. Any non-space character
+//! in a wall position counts as a wall, so hand edited mazes do not need to be
+//! exact.
+
+use std::{fmt, str::FromStr};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::*,
+    grid::{Dir, Grid},
+};
+
+const EXPORT_PATH: &str = "maze.txt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    LineCount(usize),
+    LineLength { line: usize },
+    OpenBorder { line: usize, column: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "the maze is empty"),
+            ParseError::LineCount(n) => write!(f, "expected an odd number of lines, found {n}"),
+            ParseError::LineLength { line } => {
+                write!(f, "line {} has the wrong length", line + 1)
+            }
+            ParseError::OpenBorder { line, column } => write!(
+                f,
+                "the outer wall is open at line {}, column {}",
+                line + 1,
+                column + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, export);
+}
+
+impl Grid {
+    pub fn to_ascii(&self) -> String {
+        let mut s = String::new();
+
+        for y in (0..self.height() as i32).rev() {
+            for x in 0..self.width() as i32 {
+                s.push('+');
+                s.push_str(if self.has_wall(IVec2::new(x, y), Dir::North) {
+                    "--"
+                } else {
+                    "  "
+                });
+            }
+            s.push_str("+\n");
+
+            for x in 0..self.width() as i32 {
+                s.push(if self.has_wall(IVec2::new(x, y), Dir::West) {
+                    '|'
+                } else {
+                    ' '
+                });
+                s.push_str("  ");
+            }
+            s.push_str("|\n");
+        }
+
+        for _ in 0..self.width() {
+            s.push_str("+--");
+        }
+        s.push_str("+\n");
+
+        s
+    }
+
+    pub fn from_ascii(s: &str) -> Result<Self, ParseError> {
+        // Blank lines are skipped, but errors refer to the lines of `s`
+        let (numbers, lines): (Vec<usize>, Vec<&[u8]>) = s
+            .lines()
+            .map(|line| line.trim_end().as_bytes())
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .unzip();
+
+        if lines.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        if lines.len() < 3 || lines.len() % 2 == 0 {
+            return Err(ParseError::LineCount(lines.len()));
+        }
+
+        let line_len = lines[0].len();
+        if line_len < 4 || (line_len - 1) % 3 != 0 {
+            return Err(ParseError::LineLength { line: numbers[0] });
+        }
+
+        let width = (line_len - 1) / 3;
+        let height = lines.len() / 2;
+
+        // Trailing spaces are trimmed, so shorter lines are padded again
+        let is_wall = |line: usize, column: usize| -> Result<bool, ParseError> {
+            let l = lines[line];
+            if l.len() > line_len {
+                return Err(ParseError::LineLength {
+                    line: numbers[line],
+                });
+            }
+            Ok(l.get(column).is_some_and(|c| *c != b' '))
+        };
+
+        let mut grid = Grid::with_size(width, height);
+
+        for y in 0..height {
+            // Lines are numbered from the top, but the grid starts at the bottom
+            let wall_line = 2 * (height - y);
+            let cell_line = wall_line - 1;
+
+            for x in 0..width {
+                let pos = IVec2::new(x as i32, y as i32);
+
+                let south = is_wall(wall_line, 3 * x + 1)? || is_wall(wall_line, 3 * x + 2)?;
+                if !south {
+                    if y == 0 {
+                        return Err(ParseError::OpenBorder {
+                            line: numbers[wall_line],
+                            column: 3 * x + 1,
+                        });
+                    }
+                    grid.open_wall(pos, Dir::South);
+                }
+
+                if !is_wall(cell_line, 3 * x)? {
+                    if x == 0 {
+                        return Err(ParseError::OpenBorder {
+                            line: numbers[cell_line],
+                            column: 0,
+                        });
+                    }
+                    grid.open_wall(pos, Dir::West);
+                }
+            }
+
+            if !is_wall(cell_line, 3 * width)? {
+                return Err(ParseError::OpenBorder {
+                    line: numbers[cell_line],
+                    column: 3 * width,
+                });
+            }
+        }
+
+        for x in 0..width {
+            if !is_wall(0, 3 * x + 1)? && !is_wall(0, 3 * x + 2)? {
+                return Err(ParseError::OpenBorder {
+                    line: numbers[0],
+                    column: 3 * x + 1,
+                });
+            }
+        }
+
+        Ok(grid)
+    }
+}
+
+impl FromStr for Grid {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_ascii(s)
+    }
+}
+
+fn export(grid_query: Query<&Grid, With<Trees>>, input: Res<ButtonInput<KeyCode>>) {
+    if !input.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    match std::fs::write(EXPORT_PATH, grid.to_ascii()) {
+        Ok(()) => info!("Exported maze to {}", EXPORT_PATH),
+        Err(err) => error!("Could not export maze: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::maze::{self, MazeType};
+
+    #[test]
+    fn test_to_ascii() {
+        let mut grid = Grid::with_size(2, 2);
+        grid.remove_wall(IVec2::new(0, 0), Dir::North).unwrap();
+        grid.remove_wall(IVec2::new(0, 1), Dir::East).unwrap();
+
+        assert_eq!(
+            grid.to_ascii(),
+            "+--+--+\n\
+             |     |\n\
+             +  +--+\n\
+             |  |  |\n\
+             +--+--+\n"
+        );
+    }
+
+    #[test]
+    fn test_from_ascii() {
+        let grid: Grid = "+--+--+--+\n\
+                          |        |\n\
+                          +  +--+  +\n\
+                          |     |  |\n\
+                          +--+--+--+\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(grid.width(), 3);
+        assert_eq!(grid.height(), 2);
+        assert_eq!(grid.regions.num_sets(), 1);
+        assert!(!grid.has_wall(IVec2::new(0, 0), Dir::East));
+        assert!(grid.has_wall(IVec2::new(1, 0), Dir::East));
+        assert!(!grid.has_wall(IVec2::new(1, 1), Dir::West));
+        assert!(grid.has_wall(IVec2::new(1, 1), Dir::South));
+    }
+
+    #[test]
+    fn test_regions() {
+        let grid: Grid = "+--+--+--+\n\
+                          |  |     |\n\
+                          +--+--+  +\n\
+                          |     |  |\n\
+                          +--+--+--+\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(grid.regions.num_sets(), 3);
+        assert_eq!(grid.region(IVec2::new(0, 0)), grid.region(IVec2::new(1, 0)));
+        assert_eq!(grid.region(IVec2::new(1, 1)), grid.region(IVec2::new(2, 0)));
+        assert_ne!(grid.region(IVec2::new(0, 1)), grid.region(IVec2::new(1, 1)));
+        assert_ne!(grid.region(IVec2::new(0, 0)), grid.region(IVec2::new(2, 0)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!("".parse::<Grid>().err(), Some(ParseError::Empty));
+        assert_eq!(
+            "+--+\n|  |\n".parse::<Grid>().err(),
+            Some(ParseError::LineCount(2))
+        );
+        assert_eq!(
+            "+--+\n|  |  |\n+--+\n".parse::<Grid>().err(),
+            Some(ParseError::LineLength { line: 1 })
+        );
+        assert_eq!(
+            "+--+\n   |\n+--+\n".parse::<Grid>().err(),
+            Some(ParseError::OpenBorder { line: 1, column: 0 })
+        );
+        assert_eq!(
+            "+  +\n|  |\n+--+\n".parse::<Grid>().err(),
+            Some(ParseError::OpenBorder { line: 0, column: 1 })
+        );
+
+        // Blank lines are skipped but still counted
+        assert_eq!(
+            "\n+--+\n\n|  |  |\n+--+\n".parse::<Grid>().err(),
+            Some(ParseError::LineLength { line: 3 })
+        );
+        assert_eq!(
+            "\n\n+--+\n|  |\n\n+  +\n".parse::<Grid>().err(),
+            Some(ParseError::OpenBorder { line: 5, column: 1 })
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for maze_type in MazeType::ALL {
+            for (width, height) in [(1, 1), (8, 8), (13, 5)] {
+                let grid = maze::generate_grid(maze_type, width, height, &mut rng);
+                let ascii = grid.to_ascii();
+                let parsed: Grid = ascii.parse().unwrap();
+
+                assert_eq!(parsed.width(), width);
+                assert_eq!(parsed.height(), height);
+                assert_eq!(parsed.to_ascii(), ascii);
+                assert_eq!(parsed.regions.num_sets(), grid.regions.num_sets());
+                for pos in grid.positions() {
+                    assert_eq!(parsed.get_walls(pos), grid.get_walls(pos));
+                    assert_eq!(
+                        parsed.region(pos) == parsed.region(IVec2::ZERO),
+                        grid.region(pos) == grid.region(IVec2::ZERO)
+                    );
+                }
+            }
+        }
+    }
+}
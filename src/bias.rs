@@ -0,0 +1,223 @@
+//! Measure how uniformly the generators pick among all possible mazes.
+//!
+//! On a small grid every spanning tree can be enumerated, so generating many
+//! mazes and counting how often each tree comes up shows whether a generator
+//! is biased. The counts are compared to a uniform distribution with Pearson's
+//! chi-square test.
+
+use std::{collections::HashMap, fmt};
+
+use bevy::math::IVec2;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    grid::{Dir, Grid},
+    maze::{self, MazeType},
+};
+
+/// The size of the grid used by `run_bias`, which has 192 spanning trees
+pub const WIDTH: usize = 3;
+pub const HEIGHT: usize = 3;
+
+/// How often every spanning tree of a grid was generated
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiasReport {
+    pub maze_type: MazeType,
+    pub samples: usize,
+    /// The number of times each tree was generated, in the order of
+    /// `spanning_trees`
+    pub counts: Vec<usize>,
+    pub chi_square: f64,
+}
+
+/// Every spanning tree of a `width` x `height` grid, as the wall bits returned
+/// by `Grid::walls`. This tries every subset of the edges, so it is only
+/// usable for grids of about 12 cells or less.
+pub fn spanning_trees(width: usize, height: usize) -> Vec<Vec<u8>> {
+    let empty = Grid::with_size(width, height);
+    let edges: Vec<(IVec2, Dir)> = empty
+        .positions()
+        .flat_map(|pos| [(pos, Dir::North), (pos, Dir::East)])
+        .filter(|(pos, dir)| empty.is_inside(*pos + IVec2::from(*dir)))
+        .collect();
+    assert!(edges.len() < 32, "The grid is too large to enumerate");
+
+    let num_cells = width * height;
+    let mut trees = Vec::new();
+    for subset in 0u32..1 << edges.len() {
+        if subset.count_ones() as usize != num_cells - 1 {
+            continue;
+        }
+
+        // With cells - 1 edges and no loops the subset is a spanning tree
+        let mut grid = empty.clone();
+        let is_tree = edges
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| subset & 1 << i != 0)
+            .all(|(_, (pos, dir))| grid.remove_wall(*pos, *dir).is_ok());
+
+        if is_tree {
+            trees.push(grid.walls().to_vec());
+        }
+    }
+    trees
+}
+
+/// Pearson's chi-square statistic of `counts` against a uniform distribution
+pub fn chi_square(counts: &[usize]) -> f64 {
+    let total: usize = counts.iter().sum();
+    let expected = total as f64 / counts.len() as f64;
+    counts
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+/// How many standard deviations a chi-square statistic with `k` degrees of
+/// freedom is above its expected value, using the Wilson-Hilferty
+/// approximation. Values above about 3 mean the counts are very unlikely to be
+/// uniform.
+pub fn z_score(chi_square: f64, k: usize) -> f64 {
+    let k = k as f64;
+    let variance = 2.0 / (9.0 * k);
+    ((chi_square / k).cbrt() - (1.0 - variance)) / variance.sqrt()
+}
+
+impl BiasReport {
+    /// Generate `samples` mazes of the given size, starting from `seed`
+    pub fn new(
+        maze_type: MazeType,
+        width: usize,
+        height: usize,
+        samples: usize,
+        seed: u64,
+    ) -> Self {
+        let trees = spanning_trees(width, height);
+        let indices: HashMap<&[u8], usize> = trees
+            .iter()
+            .enumerate()
+            .map(|(i, walls)| (&walls[..], i))
+            .collect();
+
+        let mut counts = vec![0; trees.len()];
+        for sample in 0..samples as u64 {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(sample));
+            let grid = maze::generate_grid(maze_type, width, height, &mut rng);
+            let index = indices[grid.walls()];
+            counts[index] += 1;
+        }
+
+        Self {
+            maze_type,
+            samples,
+            chi_square: chi_square(&counts),
+            counts,
+        }
+    }
+
+    pub fn degrees_of_freedom(&self) -> usize {
+        self.counts.len() - 1
+    }
+
+    /// The number of trees which were never generated
+    pub fn missing(&self) -> usize {
+        self.counts.iter().filter(|&&count| count == 0).count()
+    }
+
+    /// The `z_score` of the chi-square statistic
+    pub fn z_score(&self) -> f64 {
+        z_score(self.chi_square, self.degrees_of_freedom())
+    }
+}
+
+impl fmt::Display for BiasReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let min = self.counts.iter().min().copied().unwrap_or(0);
+        let max = self.counts.iter().max().copied().unwrap_or(0);
+        write!(
+            f,
+            "{:<14}{:>10}{:>10}{:>10}{:>10}{:>14.1}{:>6}{:>10.1}",
+            format!("{:?}", self.maze_type),
+            self.samples,
+            self.missing(),
+            min,
+            max,
+            self.chi_square,
+            self.degrees_of_freedom(),
+            self.z_score()
+        )
+    }
+}
+
+/// Print a bias report for every generator on a `WIDTH` x `HEIGHT` grid
+pub fn run_bias(samples_per_tree: usize) {
+    let samples = samples_per_tree * spanning_trees(WIDTH, HEIGHT).len();
+
+    println!(
+        "{:<14}{:>10}{:>10}{:>10}{:>10}{:>14}{:>6}{:>10}",
+        "Generator", "Samples", "Missing", "Min", "Max", "Chi-square", "df", "z"
+    );
+    for maze_type in MazeType::ALL {
+        println!("{}", BiasReport::new(maze_type, WIDTH, HEIGHT, samples, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_spanning_trees() {
+        assert_eq!(spanning_trees(1, 1).len(), 1);
+        assert_eq!(spanning_trees(4, 1).len(), 1);
+        assert_eq!(spanning_trees(2, 2).len(), 4);
+        assert_eq!(spanning_trees(3, 2).len(), 15);
+        assert_eq!(spanning_trees(3, 3).len(), 192);
+
+        for walls in spanning_trees(3, 2) {
+            let grid = Grid::from_walls(3, 2, &walls);
+            assert_eq!(grid.regions.num_sets(), 1);
+        }
+    }
+
+    #[test]
+    fn test_chi_square() {
+        assert_eq!(chi_square(&[5, 5, 5, 5]), 0.0);
+        assert_eq!(chi_square(&[10, 0]), 10.0);
+        assert_eq!(chi_square(&[6, 4, 2]), 2.0);
+    }
+
+    #[test]
+    fn test_bias() {
+        let reports: Vec<BiasReport> = MazeType::ALL
+            .into_iter()
+            .map(|maze_type| BiasReport::new(maze_type, WIDTH, HEIGHT, 192 * 20, 0))
+            .collect();
+        for report in &reports {
+            assert_eq!(report.counts.iter().sum::<usize>(), report.samples);
+        }
+
+        // Drawing every tree with the same probability stays below a z score
+        // of 3, none of the generators do
+        let trees = spanning_trees(WIDTH, HEIGHT);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = vec![0; trees.len()];
+        for _ in 0..192 * 20 {
+            counts[rng.gen_range(0..trees.len())] += 1;
+        }
+        assert!(z_score(chi_square(&counts), trees.len() - 1) < 3.0);
+
+        let [backtracking, kruskal, wfc] = &reports[..] else {
+            panic!("There should be a report for every generator");
+        };
+        assert!(backtracking.missing() > 0);
+        assert!(backtracking.z_score() > 3.0);
+        assert_eq!(kruskal.missing(), 0);
+        assert!(kruskal.z_score() > 3.0);
+        assert_eq!(wfc.missing(), 0);
+        assert!(wfc.z_score() > 3.0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Generate mazes without opening a window
+
+use std::{io::Write, path::PathBuf};
+
+use bevy::math::IVec2;
+use clap::{Parser, ValueEnum};
+use maze::{
+    maze::{
+        self as generator, CursorConfig, CursorStart, EdgeWeights, GeneratorConfig, Level, MazeType,
+    },
+    print::{self, PrintOptions},
+    save::SaveData,
+    solver,
+    stats::MazeStats,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Ascii,
+    Svg,
+    Png,
+    Json,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Generate mazes without opening a window")]
+struct Args {
+    /// The generator to use: backtracking, kruskal or wfc
+    #[arg(short, long, default_value = "backtracking")]
+    algorithm: MazeType,
+
+    #[arg(long, default_value_t = 16)]
+    width: usize,
+
+    #[arg(long, default_value_t = 16)]
+    height: usize,
+
+    /// The seed for the random number generator. A random seed is used if
+    /// this is not given.
+    #[arg(short, long)]
+    seed: Option<u64>,
+
+    /// The number of cursors of the backtracking generator
+    #[arg(long, default_value_t = 4)]
+    cursors: usize,
+
+    /// Where the cursors start: random, corners, even or a list of cells
+    /// such as "0,0;5,7"
+    #[arg(long, default_value = "corners")]
+    cursor_start: CursorStart,
+
+    /// The order in which Kruskal removes walls: uniform, horizontal,
+    /// vertical, noise or the path of an image whose dark parts are joined
+    /// first
+    #[arg(long, default_value = "uniform")]
+    weights: String,
+
+    /// The fraction of dead ends to remove
+    #[arg(short, long, default_value_t = 0.0)]
+    braid: f32,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Ascii)]
+    format: Format,
+
+    /// Write to this file instead of standard output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Print statistics about the maze to standard error
+    #[arg(long)]
+    stats: bool,
+
+    /// Draw the solution in SVG and PNG output
+    #[arg(long)]
+    solution: bool,
+
+    #[arg(long, default_value_t = 32)]
+    cell_size: u32,
+
+    #[arg(long, default_value_t = 2)]
+    line_width: u32,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if args.width == 0 || args.height == 0 {
+        eprintln!("The maze must be at least 1x1");
+        std::process::exit(1);
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let weights = match args.weights.as_str() {
+        "uniform" => EdgeWeights::Uniform,
+        "horizontal" => EdgeWeights::directional(0.5, seed),
+        "vertical" => EdgeWeights::directional(-0.5, seed),
+        "noise" => EdgeWeights::noise(args.width.max(args.height) as f32 / 4.0, seed),
+        path => EdgeWeights::from_image(path).unwrap_or_else(|err| {
+            eprintln!("Could not read the weight image {}: {}", path, err);
+            std::process::exit(1);
+        }),
+    };
+    let config = GeneratorConfig {
+        cursors: CursorConfig {
+            count: args.cursors,
+            start: args.cursor_start.clone(),
+        },
+        weights,
+    };
+    let mut grid =
+        generator::generate_grid_with(args.algorithm, args.width, args.height, &config, &mut rng)
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid cursors: {}", err);
+                std::process::exit(1);
+            });
+    generator::braid(&mut grid, args.braid, &mut rng);
+
+    let start = IVec2::ZERO;
+    let goal = IVec2::new(args.width as i32 - 1, args.height as i32 - 1);
+
+    let options = PrintOptions {
+        cell_size: args.cell_size,
+        line_width: args.line_width,
+        solution: args
+            .solution
+            .then(|| solver::solve(&grid, start, goal))
+            .flatten(),
+        start: Some(start),
+        goal: Some(goal),
+    };
+
+    let level = Level {
+        maze_type: args.algorithm,
+        seed,
+        braid: args.braid,
+    };
+
+    let output = match args.format {
+        Format::Ascii => grid.to_ascii().into_bytes(),
+        Format::Svg => print::to_svg(&grid, &options).into_bytes(),
+        Format::Png => print::to_png(&grid, &options),
+        Format::Json => SaveData::new(&grid, &level)
+            .to_json()
+            .expect("Serialising a level should not fail")
+            .into_bytes(),
+    };
+
+    let result = match &args.output {
+        Some(path) => std::fs::write(path, output),
+        None => std::io::stdout().write_all(&output),
+    };
+    if let Err(err) = result {
+        eprintln!("Could not write the maze: {}", err);
+        std::process::exit(1);
+    }
+
+    if args.stats {
+        eprintln!("Algorithm: {:?}", args.algorithm);
+        eprintln!("Seed: {}", seed);
+        eprintln!("{}", MazeStats::new(&grid, start, goal));
+    }
+}
@@ -12,14 +12,6 @@ pub struct Trees;
 #[derive(Component)]
 pub struct Ground;
 
-#[derive(Component, Default)]
-pub struct Cover(f32);
-
-impl Cover {
-    pub fn step(&mut self, dt: f32) -> u8 {
-        self.0 += dt * 60.0;
-        let inc = self.0.floor();
-        self.0 -= inc;
-        inc as u8
-    }
-}
+/// Time spent playing the current level, in seconds
+#[derive(Resource, Default)]
+pub struct ElapsedTime(pub f32);
@@ -0,0 +1,228 @@
+//! The cover hiding the cells of the maze which were not visited yet. A cell
+//! fades in on the GPU from the time it was revealed, using the time in
+//! Bevy's globals uniform, so the cover only changes when cells are revealed
+//! or hidden and when their fade is over.
+
+use std::collections::HashMap;
+
+use bevy::{math::URect, prelude::*};
+
+use crate::{
+    grid::Grid,
+    states::AppState,
+    tilemap::{self, Tile, Tilemap, TilemapData, TilemapMaterialShader},
+};
+
+/// A cell which is hidden
+pub const COVERED: u8 = 0;
+/// A cell which is fully revealed. The values in between are the time the
+/// cell was revealed, see `stamp`.
+pub const REVEALED: u8 = 255;
+
+/// The fade takes one tick per step of the cover tileset
+const TICKS_PER_SECOND: f32 = 60.0;
+/// The number of different reveal times, which wrap around
+const STAMPS: u32 = REVEALED as u32 - 1;
+
+#[derive(Component)]
+pub struct Cover {
+    tilemap: Tilemap,
+    /// The number of ticks a fade takes, one less than the number of tiles
+    fade_ticks: u32,
+    /// The time each cell which is still fading in was revealed, by index
+    fading: HashMap<usize, f32>,
+}
+
+#[derive(TypePath, Clone)]
+pub struct CoverShader;
+
+impl TilemapMaterialShader for CoverShader {
+    const SHADER: &'static str = "shaders/cover.wgsl";
+}
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins((
+        tilemap::register_shader::<CoverShader>,
+        tilemap::register_data::<CoverShader, Cover>,
+    ))
+    .add_systems(Update, update.run_if(in_state(AppState::InGame)));
+}
+
+/// The tile of a cell revealed at `now` seconds, which `cover.wgsl` turns
+/// back into the time since then. `now` wraps around like `globals.time`.
+pub fn stamp(now: f32) -> u8 {
+    ((now * TICKS_PER_SECOND) as u32 % STAMPS + 1) as u8
+}
+
+fn position(width: usize, index: usize) -> UVec2 {
+    UVec2::new((index % width) as u32, (index / width) as u32)
+}
+
+impl Cover {
+    /// A cover of `width` by `height` cells using a tileset of `num_tiles`
+    /// tiles, from fully covered to fully revealed
+    pub fn new(width: u32, height: u32, num_tiles: u32) -> Self {
+        Self {
+            tilemap: Tilemap::new(width, height),
+            fade_ticks: num_tiles - 1,
+            fading: HashMap::new(),
+        }
+    }
+
+    fn position(&self, index: usize) -> UVec2 {
+        position(self.tilemap.grid_size.x as usize, index)
+    }
+
+    /// Reveal the visited cells of `grid` and cover the others. Only the cells
+    /// which changed are written.
+    pub fn reveal(&mut self, grid: &Grid, now: f32) {
+        for index in 0..grid.num_cells() {
+            let pos = self.position(index);
+            let covered = self.tilemap.get(pos) == Tile::from(COVERED);
+            if grid.regions.is_singleton(index) {
+                if !covered {
+                    self.tilemap.set(pos, COVERED);
+                    self.fading.remove(&index);
+                }
+            } else if covered {
+                self.tilemap.set(pos, stamp(now));
+                self.fading.insert(index, now);
+            }
+        }
+    }
+
+    pub fn is_fading(&self) -> bool {
+        !self.fading.is_empty()
+    }
+
+    /// Mark the cells whose fade is over as revealed, before their stamps
+    /// come around again
+    pub fn finish_fades(&mut self, now: f32) {
+        let duration = self.fade_ticks as f32 / TICKS_PER_SECOND;
+        let width = self.tilemap.grid_size.x as usize;
+        let tilemap = &mut self.tilemap;
+        self.fading.retain(|index, revealed| {
+            // When the time wraps around the fade is over as well
+            if (0.0..duration).contains(&(now - *revealed)) {
+                return true;
+            }
+            tilemap.set(position(width, *index), REVEALED);
+            false
+        });
+    }
+
+    /// The cover as it is saved, with every cell either covered or revealed
+    pub fn to_saved(&self) -> Vec<u8> {
+        self.tilemap
+            .data()
+            .iter()
+            .map(|tile| {
+                if *tile == Tile::from(COVERED) {
+                    COVERED
+                } else {
+                    REVEALED
+                }
+            })
+            .collect()
+    }
+
+    /// Restore a saved cover. Any cell which is not covered is revealed, which
+    /// also accepts the fade steps stored by older versions.
+    pub fn load(&mut self, saved: &[u8]) {
+        let tiles: Vec<Tile> = saved
+            .iter()
+            .map(|tile| Tile::from(if *tile == COVERED { COVERED } else { REVEALED }))
+            .collect();
+        self.tilemap.copy_from(&tiles);
+        self.fading.clear();
+    }
+}
+
+impl TilemapData for Cover {
+    type Item = Tile;
+
+    fn data(&self) -> &[Tile] {
+        self.tilemap.data()
+    }
+
+    fn size(&self) -> Vec4 {
+        self.tilemap.size()
+    }
+
+    fn dirty(&self) -> Option<URect> {
+        self.tilemap.dirty()
+    }
+
+    fn clear_dirty(&mut self) {
+        self.tilemap.clear_dirty();
+    }
+}
+
+fn update(grid_query: Query<Ref<Grid>>, mut cover_query: Query<&mut Cover>, time: Res<Time>) {
+    let (Ok(grid), Ok(mut cover)) = (grid_query.get_single(), cover_query.get_single_mut()) else {
+        return;
+    };
+
+    // The same time as `globals.time` in the shader
+    let now = time.elapsed_seconds_wrapped();
+    if grid.is_changed() {
+        cover.reveal(&grid, now);
+    }
+    if cover.is_fading() {
+        cover.finish_fades(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grid::Dir;
+
+    use super::*;
+
+    fn indices(cover: &Cover) -> Vec<u8> {
+        cover.data().iter().map(|tile| tile.index as u8).collect()
+    }
+
+    #[test]
+    fn test_stamp() {
+        assert_eq!(stamp(0.0), 1);
+        assert_eq!(stamp(1.0), 61);
+        assert_eq!(stamp((STAMPS as f32 + 0.5) / TICKS_PER_SECOND), 1);
+        assert!((0..10000).all(|n| !matches!(stamp(n as f32 * 0.37), COVERED | REVEALED)));
+    }
+
+    #[test]
+    fn test_reveal() {
+        let mut grid = Grid::with_size(3, 1);
+        grid.open_wall(IVec2::new(0, 0), Dir::East);
+        let mut cover = Cover::new(3, 1, 5);
+        cover.clear_dirty();
+
+        cover.reveal(&grid, 1.0);
+        assert_eq!(indices(&cover), [61, 61, COVERED]);
+        assert_eq!(cover.dirty(), Some(URect::new(0, 0, 2, 1)));
+        assert_eq!(cover.to_saved(), [REVEALED, REVEALED, COVERED]);
+
+        // Revealing again changes nothing while the fade is running
+        cover.clear_dirty();
+        cover.reveal(&grid, 1.01);
+        cover.finish_fades(1.05);
+        assert_eq!(cover.dirty(), None);
+        assert!(cover.is_fading());
+
+        cover.finish_fades(1.1);
+        assert_eq!(indices(&cover), [REVEALED, REVEALED, COVERED]);
+        assert!(!cover.is_fading());
+
+        grid.open_wall(IVec2::new(1, 0), Dir::East);
+        cover.reveal(&grid, 2.0);
+        assert_eq!(indices(&cover), [REVEALED, REVEALED, 121]);
+        grid.close_wall(IVec2::new(1, 0), Dir::East);
+        cover.reveal(&grid, 2.01);
+        assert_eq!(indices(&cover), [REVEALED, REVEALED, COVERED]);
+        assert!(!cover.is_fading());
+
+        cover.load(&[3, 0, 200]);
+        assert_eq!(indices(&cover), [REVEALED, COVERED, REVEALED]);
+    }
+}
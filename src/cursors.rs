@@ -0,0 +1,177 @@
+//! A layer highlighting the cells the generator is working on while the maze
+//! is being generated. Generators running on a background task are not shown.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{
+    consts::*,
+    maze::{ActiveCell, MazeState},
+    states::GamePlayState,
+    tilemap::{Tile, Tilemap, TilemapData, Tileset},
+};
+
+/// The number of distinct cursor colours, further cursors repeat them
+pub const NUM_CURSOR_COLORS: usize = 64;
+const FRONTIER_COLOR: [u8; 3] = [0xff, 0xff, 0xff];
+const CANDIDATE_COLOR: [u8; 3] = [0x50, 0xdc, 0x78];
+
+const HEAD_ALPHA: u8 = 0xe0;
+const STACK_ALPHA: u8 = 0x60;
+const REGION_ALPHA: u8 = 0x30;
+
+#[derive(Component)]
+pub struct CursorLayer;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            update.run_if(in_state(GamePlayState::GeneratingMaze)),
+        )
+        .add_systems(OnExit(GamePlayState::GeneratingMaze), clear);
+}
+
+/// The colour of cursor `n`. The hues are a golden angle apart, so that
+/// cursors with nearby indices look different, and every 16 cursors the
+/// lightness changes.
+pub fn cursor_color(n: usize) -> [u8; 3] {
+    const LIGHTNESS: [f32; 4] = [0.55, 0.75, 0.4, 0.85];
+    let n = n % NUM_CURSOR_COLORS;
+    let hue = n as f32 * 137.508 % 360.0;
+    let lightness = LIGHTNESS[n / 16 % LIGHTNESS.len()];
+    let [r, g, b, _] = Color::hsl(hue, 0.75, lightness).to_srgba().to_u8_array();
+    [r, g, b]
+}
+
+/// The tile showing `cell`. Tile 0 is transparent.
+pub fn tile(cell: ActiveCell) -> u8 {
+    let cursor_tile =
+        |layer: usize, n: usize| (1 + layer * NUM_CURSOR_COLORS + n % NUM_CURSOR_COLORS) as u8;
+    match cell {
+        ActiveCell::Head(n) => cursor_tile(0, n),
+        ActiveCell::Stack(n) => cursor_tile(1, n),
+        ActiveCell::Region(n) => cursor_tile(2, n),
+        ActiveCell::Frontier => cursor_tile(3, 0),
+        ActiveCell::Candidate => cursor_tile(3, 1),
+    }
+}
+
+fn create_tileset(images: &mut Assets<Image>) -> Tileset {
+    let mut colors = vec![[0, 0, 0, 0]];
+    for alpha in [HEAD_ALPHA, STACK_ALPHA, REGION_ALPHA] {
+        colors.extend((0..NUM_CURSOR_COLORS).map(|n| {
+            let [r, g, b] = cursor_color(n);
+            [r, g, b, alpha]
+        }));
+    }
+    for [r, g, b] in [FRONTIER_COLOR, CANDIDATE_COLOR] {
+        colors.push([r, g, b, HEAD_ALPHA]);
+    }
+
+    let image = Image::new(
+        Extent3d {
+            width: 1,
+            height: colors.len() as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        colors.concat(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::all(),
+    );
+
+    Tileset {
+        image: images.add(image),
+        num_tiles: colors.len() as u32,
+    }
+}
+
+fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    commands.spawn((
+        create_tileset(&mut images),
+        Tilemap::new(GRID_WIDTH as u32, GRID_HEIGHT as u32),
+        Transform::default().with_translation(Vec3::new(0.0, 0.0, 12.0)),
+        CursorLayer,
+        Name::from("Tilemap: Cursors"),
+    ));
+}
+
+fn update(state_query: Query<&MazeState>, mut layer_query: Query<&mut Tilemap, With<CursorLayer>>) {
+    let Ok(mut tilemap) = layer_query.get_single_mut() else {
+        return;
+    };
+
+    // Only the tiles which differ from the last frame are uploaded
+    let width = tilemap.grid_size.x as usize;
+    let mut data = vec![Tile::default(); tilemap.data().len()];
+    for state in state_query.iter() {
+        for (pos, cell) in state.active_cells() {
+            data[pos.y as usize * width + pos.x as usize] = tile(cell).into();
+        }
+    }
+    tilemap.copy_from(&data);
+}
+
+fn clear(mut layer_query: Query<&mut Tilemap, With<CursorLayer>>) {
+    for mut tilemap in layer_query.iter_mut() {
+        tilemap.fill(Tile::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{grid::Grid, maze::MazeType};
+
+    #[test]
+    fn test_tiles() {
+        let tiles = [
+            tile(ActiveCell::Head(0)),
+            tile(ActiveCell::Head(63)),
+            tile(ActiveCell::Stack(0)),
+            tile(ActiveCell::Region(63)),
+            tile(ActiveCell::Frontier),
+            tile(ActiveCell::Candidate),
+        ];
+        assert_eq!(tiles, [1, 64, 65, 192, 193, 194]);
+        assert_eq!(tile(ActiveCell::Head(64)), tile(ActiveCell::Head(0)));
+
+        let mut images = Assets::<Image>::default();
+        let tileset = create_tileset(&mut images);
+        assert_eq!(tileset.num_tiles, 195);
+    }
+
+    #[test]
+    fn test_colors() {
+        let colors: Vec<[u8; 3]> = (0..NUM_CURSOR_COLORS).map(cursor_color).collect();
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[..i] {
+                let distance: i32 = (0..3).map(|c| (a[c] as i32 - b[c] as i32).abs()).sum();
+                assert!(distance > 8, "{:?} and {:?} look the same", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_active_cells() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for maze_type in MazeType::ALL {
+            let mut grid = Grid::with_size(8, 8);
+            let mut state = MazeState::new(maze_type, 8, 8, &mut rng);
+            for _ in 0..10 {
+                state.step(&mut grid, &mut rng);
+                let cells = state.active_cells();
+                assert!(!cells.is_empty(), "{:?} has no active cells", maze_type);
+                assert!(cells.iter().all(|(pos, _)| grid.is_inside(*pos)));
+            }
+        }
+    }
+}
@@ -1,23 +1,106 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Entry {
-    Root { size: usize },
-    Link { index: usize },
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod keyed;
+mod undoable;
+
+pub use keyed::KeyedDisjointSet;
+pub use undoable::{Checkpoint, UndoableDisjointSet};
+
+/// The operations the grid needs from its set of regions
+pub trait UnionFind: Clone + Send + Sync + 'static {
+    /// Whether the wall changes of a grid using this should be recorded, so
+    /// that they can be rolled back together with the regions
+    const UNDOABLE: bool = false;
+
+    fn new(len: usize) -> Self;
+    fn len(&self) -> usize;
+    fn find(&self, index: usize) -> usize;
+    fn join(&mut self, a: usize, b: usize);
+    fn num_sets(&self) -> usize;
+    fn num_members(&self, index: usize) -> usize;
+    fn is_singleton(&self, index: usize) -> bool;
+    fn sets(&self) -> impl Iterator<Item = usize> + '_;
+    fn members(&self, index: usize) -> impl Iterator<Item = usize> + '_;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A union-find structure over the indices `0..len`.
+///
+/// Sets are joined by size and `find` uses path halving, so that both are
+/// amortised near-constant time. The parent links are atomic so that `find`
+/// can compress paths through a shared reference.
+///
+/// The members of every set are also linked into a circular list and the
+/// roots are kept in a list of their own, so that the sets and their members
+/// can be iterated without scanning every index.
+#[derive(Debug)]
 pub struct DisjointSet {
-    entries: Vec<Entry>,
+    parents: Vec<AtomicUsize>,
+    sizes: Vec<usize>,
     num_sets: usize,
+    /// The next member of the same set
+    next: Vec<usize>,
+    roots: Vec<usize>,
+    /// The position of every root in `roots`
+    slots: Vec<usize>,
+}
+
+/// Iterate the circular list of members starting at `index`
+fn members(next: &[usize], index: usize) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(Some(index), move |&i| Some(next[i]).filter(|&n| n != index))
+}
+
+/// Remove `root` from the list of roots in constant time
+fn remove_root(roots: &mut Vec<usize>, slots: &mut [usize], root: usize) {
+    let slot = slots[root];
+    roots.swap_remove(slot);
+    if let Some(&moved) = roots.get(slot) {
+        slots[moved] = slot;
+    }
+}
+
+impl Clone for DisjointSet {
+    fn clone(&self) -> Self {
+        Self {
+            parents: (0..self.len())
+                .map(|i| AtomicUsize::new(self.parent(i)))
+                .collect(),
+            sizes: self.sizes.clone(),
+            num_sets: self.num_sets,
+            next: self.next.clone(),
+            roots: self.roots.clone(),
+            slots: self.slots.clone(),
+        }
+    }
 }
 
 impl DisjointSet {
     pub fn new(len: usize) -> Self {
         Self {
-            entries: vec![Entry::Root { size: 1 }; len],
+            parents: (0..len).map(AtomicUsize::new).collect(),
+            sizes: vec![1; len],
             num_sets: len,
+            next: (0..len).collect(),
+            roots: (0..len).collect(),
+            slots: (0..len).collect(),
         }
     }
 
+    /// Add a new set containing only the returned index
+    pub fn push(&mut self) -> usize {
+        let index = self.len();
+        self.parents.push(AtomicUsize::new(index));
+        self.sizes.push(1);
+        self.num_sets += 1;
+        self.next.push(index);
+        self.slots.push(self.roots.len());
+        self.roots.push(index);
+        index
+    }
+
     pub fn values(&self) -> impl Iterator<Item = usize> + '_ {
         (0..self.len()).map(|i| self.find(i))
     }
@@ -27,84 +110,120 @@ impl DisjointSet {
     }
 
     pub fn num_members(&self, index: usize) -> usize {
-        match self.entries[self.find(index)] {
-            Entry::Root { size } => size,
-            Entry::Link { index: _ } => unreachable!(),
-        }
+        self.sizes[self.find(index)]
     }
 
     pub fn len(&self) -> usize {
-        self.entries.len()
+        self.parents.len()
     }
 
-    pub fn find(&self, index: usize) -> usize {
-        let mut i = index;
-        loop {
-            match self.entries[i] {
-                Entry::Root { size: _ } => break i,
-                Entry::Link { index: j } => i = j,
-            }
-        }
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        self.parents[index].load(Ordering::Relaxed)
+    }
+
+    fn is_root(&self, index: usize) -> bool {
+        self.parent(index) == index
     }
 
-    fn compress(&mut self, index: usize, root: usize) {
+    pub fn find(&self, index: usize) -> usize {
         let mut i = index;
         loop {
-            match self.entries[i] {
-                Entry::Root { size: _ } => break,
-                Entry::Link { index: j } => {
-                    self.entries[i] = Entry::Link { index: root };
-                    i = j;
-                }
+            let parent = self.parent(i);
+            if parent == i {
+                break i;
             }
+            // Path halving: point every other node on the path to its
+            // grandparent. This only ever moves a link closer to the root, so
+            // concurrent calls still see a valid tree.
+            let grandparent = self.parent(parent);
+            self.parents[i].store(grandparent, Ordering::Relaxed);
+            i = grandparent;
         }
     }
 
-    pub fn join(&mut self, mut a: usize, mut b: usize) {
+    pub fn join(&mut self, a: usize, b: usize) {
         let mut a_root = self.find(a);
         let mut b_root = self.find(b);
         if a_root == b_root {
             return;
         }
-        let a_size = self.num_members(a_root);
-        let b_size = self.num_members(b_root);
 
-        if a_size < b_size {
-            std::mem::swap(&mut a, &mut b);
+        if self.sizes[a_root] < self.sizes[b_root] {
             std::mem::swap(&mut a_root, &mut b_root);
         }
 
-        match self.entries[a_root] {
-            Entry::Root { size: _ } => {
-                self.entries[b_root] = Entry::Link { index: a_root };
-                self.entries[a_root] = Entry::Root {
-                    size: a_size + b_size,
-                };
-                self.num_sets -= 1;
-            }
-            Entry::Link { index: _ } => unreachable!("a should be root"),
-        }
-
-        self.compress(a, a_root);
-        self.compress(b, a_root);
+        *self.parents[b_root].get_mut() = a_root;
+        self.sizes[a_root] += self.sizes[b_root];
+        self.num_sets -= 1;
+        // Swapping the successors splices the two circular lists together
+        self.next.swap(a_root, b_root);
+        remove_root(&mut self.roots, &mut self.slots, b_root);
     }
 
     pub fn is_singleton(&self, index: usize) -> bool {
-        matches!(self.entries[index], Entry::Root { size: 1 })
+        self.is_root(index) && self.sizes[index] == 1
+    }
+
+    /// The root of every set, in no particular order
+    pub fn sets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.roots.iter().copied()
+    }
+
+    /// Every member of the set containing `index`, starting with `index`
+    pub fn members(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        members(&self.next, index)
     }
 
     pub fn depth(&self, index: usize) -> usize {
         let mut i = index;
         let mut depth = 0;
-        loop {
-            match self.entries[i] {
-                Entry::Root { size: _ } => break depth,
-                Entry::Link { index: j } => {
-                    i = j;
-                    depth += 1;
-                }
-            }
+        while !self.is_root(i) {
+            i = self.parent(i);
+            depth += 1;
         }
+        depth
+    }
+}
+
+impl UnionFind for DisjointSet {
+    fn new(len: usize) -> Self {
+        Self::new(len)
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn find(&self, index: usize) -> usize {
+        self.find(index)
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        self.join(a, b)
+    }
+
+    fn num_sets(&self) -> usize {
+        self.num_sets()
+    }
+
+    fn num_members(&self, index: usize) -> usize {
+        self.num_members(index)
+    }
+
+    fn is_singleton(&self, index: usize) -> bool {
+        self.is_singleton(index)
+    }
+
+    fn sets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sets()
+    }
+
+    fn members(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.members(index)
     }
 }
 
@@ -123,18 +242,26 @@ mod tests {
         assert!(ds.is_singleton(1));
     }
 
+    /// A set with the given parent links. The member lists are not built.
+    fn with_parents<const N: usize>(parents: [usize; N], sizes: Vec<usize>) -> DisjointSet {
+        let roots: Vec<usize> = (0..N).filter(|&i| parents[i] == i).collect();
+        let mut slots = vec![0; N];
+        for (slot, &root) in roots.iter().enumerate() {
+            slots[root] = slot;
+        }
+        DisjointSet {
+            parents: parents.map(AtomicUsize::new).into(),
+            sizes,
+            num_sets: roots.len(),
+            next: (0..N).collect(),
+            roots,
+            slots,
+        }
+    }
+
     #[test]
     fn test_root_of() {
-        let ds = DisjointSet {
-            entries: vec![
-                Entry::Root { size: 4 },
-                Entry::Link { index: 0 },
-                Entry::Link { index: 1 },
-                Entry::Link { index: 0 },
-                Entry::Root { size: 1 },
-            ],
-            num_sets: 2,
-        };
+        let ds = with_parents([0, 0, 1, 0, 4], vec![4, 1, 1, 1, 1]);
 
         assert_eq!(ds.find(0), 0);
         assert_eq!(ds.find(1), 0);
@@ -143,6 +270,20 @@ mod tests {
         assert_eq!(ds.find(4), 4);
     }
 
+    #[test]
+    fn test_path_halving() {
+        let ds = with_parents([0, 0, 1, 2, 3, 4], vec![6, 1, 1, 1, 1, 1]);
+
+        assert_eq!(ds.depth(5), 5);
+        assert_eq!(ds.find(5), 0);
+        assert_eq!(ds.depth(5), 3);
+        assert_eq!(ds.depth(3), 2);
+        assert_eq!(ds.find(5), 0);
+        assert_eq!(ds.depth(5), 2);
+        assert_eq!(ds.find(5), 0);
+        assert_eq!(ds.depth(5), 1);
+    }
+
     #[test]
     fn test_join() {
         let mut ds = DisjointSet::new(4);
@@ -193,9 +334,48 @@ mod tests {
         ds.join(4, 6);
         ds.join(0, 4);
 
-        for i in 0..ds.len() {
-            print!("{} ", ds.depth(i));
+        assert_eq!(ds.num_sets(), 1);
+        assert_eq!(ds.num_members(7), 8);
+        let root = ds.find(0);
+        assert!((0..ds.len()).all(|i| ds.find(i) == root));
+        // Union by size keeps the trees logarithmic
+        assert!((0..ds.len()).all(|i| ds.depth(i) <= 3));
+    }
+
+    #[test]
+    fn test_members() {
+        let mut ds = DisjointSet::new(6);
+        ds.join(0, 1);
+        ds.join(2, 3);
+        ds.join(4, 3);
+        ds.join(1, 5);
+
+        let mut sets: Vec<Vec<usize>> = ds
+            .sets()
+            .map(|root| {
+                let mut members: Vec<usize> = ds.members(root).collect();
+                members.sort();
+                members
+            })
+            .collect();
+        sets.sort();
+        assert_eq!(sets, vec![vec![0, 1, 5], vec![2, 3, 4]]);
+
+        assert_eq!(ds.members(4).next(), Some(4));
+        assert_eq!(ds.members(4).count(), 3);
+        for root in ds.sets() {
+            assert_eq!(ds.find(root), root);
         }
-        println!();
+
+        ds.join(5, 2);
+        assert_eq!(ds.sets().count(), 1);
+        assert_eq!(ds.members(3).count(), 6);
+
+        let index = ds.push();
+        assert_eq!(index, 6);
+        assert_eq!(ds.num_sets(), 2);
+        assert!(ds.is_singleton(index));
+        ds.join(index, 0);
+        assert_eq!(ds.members(index).count(), 7);
     }
 }
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, hash::Hash};
+
+use super::DisjointSet;
+
+/// A [`DisjointSet`] over arbitrary keys instead of dense indices.
+///
+/// Every key is given the next free index when it is first seen, so the sets
+/// themselves still use the dense representation.
+#[derive(Debug, Clone)]
+pub struct KeyedDisjointSet<K> {
+    indices: HashMap<K, usize>,
+    keys: Vec<K>,
+    set: DisjointSet,
+}
+
+impl<K> Default for KeyedDisjointSet<K> {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            keys: Vec::new(),
+            set: DisjointSet::new(0),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> KeyedDisjointSet<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `key` as a set of its own unless it is already present, returning
+    /// its index
+    pub fn insert(&mut self, key: K) -> usize {
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        let index = self.set.push();
+        self.indices.insert(key.clone(), index);
+        self.keys.push(key);
+        index
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn index(&self, key: &K) -> Option<usize> {
+        self.indices.get(key).copied()
+    }
+
+    pub fn key(&self, index: usize) -> &K {
+        &self.keys[index]
+    }
+
+    /// The representative key of the set containing `key`
+    pub fn find(&self, key: &K) -> Option<&K> {
+        let index = self.index(key)?;
+        Some(self.key(self.set.find(index)))
+    }
+
+    /// Join the sets containing `a` and `b`, inserting them first if needed
+    pub fn join(&mut self, a: K, b: K) {
+        let a = self.insert(a);
+        let b = self.insert(b);
+        self.set.join(a, b);
+    }
+
+    pub fn same_set(&self, a: &K, b: &K) -> bool {
+        match (self.index(a), self.index(b)) {
+            (Some(a), Some(b)) => self.set.find(a) == self.set.find(b),
+            _ => false,
+        }
+    }
+
+    pub fn num_sets(&self) -> usize {
+        self.set.num_sets()
+    }
+
+    /// The size of the set containing `key`, or 0 if it is not present
+    pub fn num_members(&self, key: &K) -> usize {
+        self.index(key)
+            .map_or(0, |index| self.set.num_members(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.keys.iter()
+    }
+
+    /// The representative key of every set, in no particular order
+    pub fn sets(&self) -> impl Iterator<Item = &K> + '_ {
+        self.set.sets().map(|index| self.key(index))
+    }
+
+    /// Every member of the set containing `key`, starting with `key`
+    pub fn members(&self, key: &K) -> impl Iterator<Item = &K> + '_ {
+        self.index(key)
+            .into_iter()
+            .flat_map(|index| self.set.members(index))
+            .map(|index| self.key(index))
+    }
+}
+
+impl<K: Hash + Eq + Clone> FromIterator<K> for KeyedDisjointSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for key in iter {
+            set.insert(key);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+
+    use super::*;
+
+    #[test]
+    fn test_cells() {
+        let mut ds: KeyedDisjointSet<IVec2> =
+            [IVec2::new(0, 0), IVec2::new(5, 5)].into_iter().collect();
+        assert_eq!(ds.len(), 2);
+        assert_eq!(ds.num_sets(), 2);
+
+        ds.join(IVec2::new(0, 0), IVec2::new(-3, 7));
+        ds.join(IVec2::new(-3, 7), IVec2::new(100, 0));
+        assert_eq!(ds.len(), 4);
+        assert_eq!(ds.num_sets(), 2);
+        assert!(ds.same_set(&IVec2::new(0, 0), &IVec2::new(100, 0)));
+        assert!(!ds.same_set(&IVec2::new(0, 0), &IVec2::new(5, 5)));
+        assert!(!ds.same_set(&IVec2::new(0, 0), &IVec2::new(1, 1)));
+        assert_eq!(ds.num_members(&IVec2::new(100, 0)), 3);
+        assert_eq!(ds.num_members(&IVec2::new(1, 1)), 0);
+        assert_eq!(ds.find(&IVec2::new(100, 0)), ds.find(&IVec2::new(-3, 7)));
+        assert_eq!(ds.find(&IVec2::new(1, 1)), None);
+    }
+
+    #[test]
+    fn test_members() {
+        let mut ds = KeyedDisjointSet::new();
+        ds.join((0, 1, 1), (0, 1, 2));
+        ds.join((1, 1, 1), (0, 1, 1));
+        ds.insert((2, 0, 0));
+
+        let mut members: Vec<_> = ds.members(&(0, 1, 2)).copied().collect();
+        members.sort();
+        assert_eq!(members, vec![(0, 1, 1), (0, 1, 2), (1, 1, 1)]);
+        assert_eq!(ds.members(&(2, 0, 0)).count(), 1);
+        assert_eq!(ds.members(&(3, 0, 0)).count(), 0);
+        assert_eq!(ds.sets().count(), 2);
+        assert_eq!(ds.insert((1, 1, 1)), ds.index(&(1, 1, 1)).unwrap());
+    }
+}
@@ -0,0 +1,246 @@
+use super::{members, remove_root, UnionFind};
+
+/// A position in the history of an [`UndoableDisjointSet`] which can be
+/// rolled back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// A union-find structure which records its joins so that they can be undone.
+///
+/// Sets are joined by size but paths are never compressed, so every join only
+/// changes the parent of a single root and can be reverted exactly. `find` is
+/// still logarithmic thanks to the union by size.
+#[derive(Debug, Clone)]
+pub struct UndoableDisjointSet {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+    num_sets: usize,
+    next: Vec<usize>,
+    roots: Vec<usize>,
+    slots: Vec<usize>,
+    /// The root which was linked below another root, for every join
+    history: Vec<usize>,
+}
+
+impl UndoableDisjointSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+            num_sets: len,
+            next: (0..len).collect(),
+            roots: (0..len).collect(),
+            slots: (0..len).collect(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn num_sets(&self) -> usize {
+        self.num_sets
+    }
+
+    pub fn num_members(&self, index: usize) -> usize {
+        self.sizes[self.find(index)]
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    pub fn find(&self, index: usize) -> usize {
+        let mut i = index;
+        while self.parents[i] != i {
+            i = self.parents[i];
+        }
+        i
+    }
+
+    pub fn join(&mut self, a: usize, b: usize) {
+        let mut a_root = self.find(a);
+        let mut b_root = self.find(b);
+        if a_root == b_root {
+            return;
+        }
+
+        if self.sizes[a_root] < self.sizes[b_root] {
+            std::mem::swap(&mut a_root, &mut b_root);
+        }
+
+        self.parents[b_root] = a_root;
+        self.sizes[a_root] += self.sizes[b_root];
+        self.num_sets -= 1;
+        self.next.swap(a_root, b_root);
+        remove_root(&mut self.roots, &mut self.slots, b_root);
+        self.history.push(b_root);
+    }
+
+    pub fn is_singleton(&self, index: usize) -> bool {
+        self.parents[index] == index && self.sizes[index] == 1
+    }
+
+    /// The root of every set, in no particular order
+    pub fn sets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.roots.iter().copied()
+    }
+
+    /// Every member of the set containing `index`, starting with `index`
+    pub fn members(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        members(&self.next, index)
+    }
+
+    /// Remember the current sets so that they can be restored with `rollback`
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.history.len())
+    }
+
+    /// Undo every join made since `checkpoint` was taken. Checkpoints taken
+    /// after it become invalid.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        assert!(
+            checkpoint.0 <= self.history.len(),
+            "Checkpoint has already been rolled back"
+        );
+
+        while self.history.len() > checkpoint.0 {
+            let b_root = self.history.pop().unwrap();
+            let a_root = self.parents[b_root];
+            self.parents[b_root] = b_root;
+            self.sizes[a_root] -= self.sizes[b_root];
+            self.num_sets += 1;
+            self.next.swap(a_root, b_root);
+
+            // The slot of a removed root is left untouched, so it can be put
+            // back where it was
+            let slot = self.slots[b_root];
+            self.roots.push(b_root);
+            let last = self.roots.len() - 1;
+            self.roots.swap(slot, last);
+            self.slots[self.roots[last]] = last;
+        }
+    }
+}
+
+impl UnionFind for UndoableDisjointSet {
+    const UNDOABLE: bool = true;
+
+    fn new(len: usize) -> Self {
+        Self::new(len)
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn find(&self, index: usize) -> usize {
+        self.find(index)
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        self.join(a, b)
+    }
+
+    fn num_sets(&self) -> usize {
+        self.num_sets()
+    }
+
+    fn num_members(&self, index: usize) -> usize {
+        self.num_members(index)
+    }
+
+    fn is_singleton(&self, index: usize) -> bool {
+        self.is_singleton(index)
+    }
+
+    fn sets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sets()
+    }
+
+    fn members(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.members(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut ds = UndoableDisjointSet::new(4);
+        let start = ds.checkpoint();
+        ds.join(0, 1);
+        let checkpoint = ds.checkpoint();
+
+        ds.join(2, 3);
+        ds.join(1, 3);
+        assert_eq!(ds.num_sets(), 1);
+        assert_eq!(ds.num_members(2), 4);
+
+        ds.rollback(checkpoint);
+        assert_eq!(ds.num_sets(), 3);
+        assert_eq!(ds.find(0), ds.find(1));
+        assert_ne!(ds.find(1), ds.find(2));
+        assert_ne!(ds.find(2), ds.find(3));
+        assert_eq!(ds.num_members(0), 2);
+        assert!(ds.is_singleton(2));
+        assert!(ds.is_singleton(3));
+
+        ds.rollback(start);
+        assert_eq!(ds.num_sets(), 4);
+        assert!((0..4).all(|i| ds.is_singleton(i)));
+    }
+
+    #[test]
+    fn test_rollback_members() {
+        let mut ds = UndoableDisjointSet::new(5);
+        ds.join(0, 1);
+        let roots: Vec<usize> = ds.sets().collect();
+        let checkpoint = ds.checkpoint();
+
+        ds.join(2, 3);
+        ds.join(1, 3);
+        ds.join(4, 0);
+        assert_eq!(ds.sets().count(), 1);
+        assert_eq!(ds.members(2).count(), 5);
+
+        ds.rollback(checkpoint);
+        assert_eq!(ds.sets().collect::<Vec<_>>(), roots);
+        let mut members: Vec<usize> = ds.members(1).collect();
+        members.sort();
+        assert_eq!(members, vec![0, 1]);
+        assert_eq!(ds.members(3).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_nested_checkpoints() {
+        let mut ds = UndoableDisjointSet::new(6);
+        let outer = ds.checkpoint();
+        ds.join(0, 1);
+        let inner = ds.checkpoint();
+        ds.join(2, 3);
+        ds.join(0, 0);
+        ds.join(1, 0);
+        assert_eq!(ds.checkpoint(), Checkpoint(2));
+
+        ds.rollback(inner);
+        assert_eq!(ds.num_sets(), 5);
+        ds.join(4, 5);
+        ds.rollback(outer);
+        assert_eq!(ds.num_sets(), 6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stale_checkpoint() {
+        let mut ds = UndoableDisjointSet::new(2);
+        let outer = ds.checkpoint();
+        ds.join(0, 1);
+        let inner = ds.checkpoint();
+        ds.rollback(outer);
+        ds.rollback(inner);
+    }
+}
@@ -0,0 +1,444 @@
+//! Editing the current maze with the mouse. F4 switches between playing and
+//! editing. Clicking next to a wall toggles it, dragging across cells carves a
+//! corridor, right clicking places the start and shift right clicking the
+//! goal. F6 saves the level if the goal can be reached.
+
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::{
+    components::*,
+    consts::*,
+    grid::{Dir, Grid},
+    history::History,
+    maze::Level,
+    save::SaveData,
+    states::GamePlayState,
+    tilemap::{TileClick, TilePointer},
+};
+
+const SAVE_PATH: &str = "edited.json";
+
+/// How close to a wall a click has to be to toggle it, in cells
+pub const EDGE_TOLERANCE: f32 = 0.3;
+
+/// Whether the maze being edited can be played
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Validation {
+    /// The number of parts of the maze which are not connected to each other
+    pub regions: usize,
+    /// The number of walls which could be added without disconnecting
+    /// anything
+    pub loops: usize,
+    pub solvable: bool,
+}
+
+impl Validation {
+    pub fn new(grid: &Grid, start: IVec2, goal: IVec2) -> Self {
+        let openings = grid
+            .positions()
+            .map(|pos| {
+                [Dir::North, Dir::East]
+                    .into_iter()
+                    .filter(|dir| !grid.has_wall(pos, *dir))
+                    .count()
+            })
+            .sum::<usize>();
+        let regions = grid.regions.num_sets();
+        let solvable = grid.is_inside(start)
+            && grid.is_inside(goal)
+            && grid.region(start) == grid.region(goal);
+
+        Self {
+            regions,
+            loops: openings + regions - grid.num_cells(),
+            solvable,
+        }
+    }
+
+    /// Every cell can be reached and there is exactly one path between any
+    /// two of them
+    pub fn is_perfect(&self) -> bool {
+        self.regions == 1 && self.loops == 0
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if !self.solvable {
+            "goal unreachable"
+        } else if self.is_perfect() {
+            "perfect maze"
+        } else {
+            "solvable"
+        };
+        write!(
+            f,
+            "Editing: {}, {} separate parts, {} loops",
+            status, self.regions, self.loops
+        )
+    }
+}
+
+/// The cell containing `point`, in grid coordinates
+pub fn cell_at(grid: &Grid, point: Vec2) -> Option<IVec2> {
+    let cell = point.floor().as_ivec2();
+    grid.is_inside(cell).then_some(cell)
+}
+
+/// The wall nearest to `point`, if it is within `EDGE_TOLERANCE` and between
+/// two cells of the grid
+pub fn edge_at(grid: &Grid, point: Vec2) -> Option<(IVec2, Dir)> {
+    let cell = cell_at(grid, point)?;
+    let local = point - cell.as_vec2();
+    let (distance, dir) = [
+        (local.x, Dir::West),
+        (1.0 - local.x, Dir::East),
+        (local.y, Dir::South),
+        (1.0 - local.y, Dir::North),
+    ]
+    .into_iter()
+    .min_by(|(a, _), (b, _)| a.total_cmp(b))?;
+
+    (distance <= EDGE_TOLERANCE && grid.is_inside(cell + IVec2::from(dir))).then_some((cell, dir))
+}
+
+/// A mouse drag. It carves a corridor through the cells it crosses, or
+/// toggles the wall it started at if it never left its first cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+    start: Vec2,
+    last: Option<IVec2>,
+    carved: bool,
+}
+
+impl Stroke {
+    pub fn new(grid: &Grid, start: Vec2) -> Self {
+        Self {
+            start,
+            last: cell_at(grid, start),
+            carved: false,
+        }
+    }
+
+    /// Continue the stroke to `point`, opening the wall to the previous cell
+    /// if it is a neighbour
+    pub fn drag(&mut self, grid: &mut Grid, point: Vec2) {
+        let Some(cell) = cell_at(grid, point) else {
+            return;
+        };
+        if let Some(last) = self.last.filter(|last| *last != cell) {
+            if let Some(dir) = Dir::ALL
+                .into_iter()
+                .find(|dir| last + IVec2::from(*dir) == cell)
+            {
+                if grid.has_wall(last, dir) {
+                    grid.open_wall(last, dir);
+                }
+                self.carved = true;
+            }
+        }
+        self.last = Some(cell);
+    }
+
+    pub fn release(self, grid: &mut Grid) {
+        if self.carved {
+            return;
+        }
+        if let Some((pos, dir)) = edge_at(grid, self.start) {
+            grid.toggle_wall(pos, dir);
+        }
+    }
+}
+
+#[derive(Component)]
+struct EditorText;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        toggle_editor
+            .run_if(in_state(GamePlayState::Playing).or_else(in_state(GamePlayState::Editing))),
+    )
+    .add_systems(OnEnter(GamePlayState::Editing), setup)
+    .add_systems(OnExit(GamePlayState::Editing), cleanup)
+    .add_systems(
+        Update,
+        (paint, place_markers, validate, update_text, save)
+            .chain()
+            .run_if(in_state(GamePlayState::Editing)),
+    );
+}
+
+fn toggle_editor(
+    state: Res<State<GamePlayState>>,
+    mut next_state: ResMut<NextState<GamePlayState>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::F4) {
+        return;
+    }
+    next_state.set(match state.get() {
+        GamePlayState::Editing => GamePlayState::Playing,
+        _ => GamePlayState::Editing,
+    });
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(MARGIN),
+            left: Val::Px(MARGIN),
+            ..default()
+        }),
+        EditorText,
+        Name::from("Editor"),
+    ));
+}
+
+fn cleanup(mut commands: Commands, text_query: Query<Entity, With<EditorText>>) {
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Validation>();
+}
+
+fn paint(
+    mut grid_query: Query<(&mut Grid, &TilePointer), With<Trees>>,
+    mut history: ResMut<History>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut stroke: Local<Option<Stroke>>,
+) {
+    let Ok((mut grid, pointer)) = grid_query.get_single_mut() else {
+        return;
+    };
+    let point = pointer.position;
+
+    if mouse.just_pressed(MouseButton::Left) {
+        *stroke = point.map(|point| Stroke::new(&grid, point));
+    }
+
+    if mouse.pressed(MouseButton::Left) {
+        if let (Some(stroke), Some(point)) = (stroke.as_mut(), point) {
+            stroke.drag(&mut grid, point);
+        }
+    }
+
+    if mouse.just_released(MouseButton::Left) {
+        if let Some(stroke) = stroke.take() {
+            stroke.release(&mut grid);
+            // Replaying the generation would no longer lead to this maze
+            history.clear();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn place_markers(
+    mut click_events: EventReader<TileClick>,
+    grid_query: Query<(), With<Trees>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<Goal>)>,
+    mut goal_query: Query<&mut Transform, (With<Goal>, Without<Player>)>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    for click in click_events.read() {
+        if click.button != MouseButton::Right || !grid_query.contains(click.entity) {
+            continue;
+        }
+
+        let shift = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        let transform = if shift {
+            goal_query.get_single_mut()
+        } else {
+            player_query.get_single_mut()
+        };
+        if let Ok(mut transform) = transform {
+            let centre = click.tile.as_vec2() + 0.5;
+            transform.translation = centre.extend(transform.translation.z);
+        }
+    }
+}
+
+/// The cell a marker is in
+fn marker_cell(transform: &Transform) -> IVec2 {
+    transform.translation.xy().floor().as_ivec2()
+}
+
+#[allow(clippy::type_complexity)]
+fn validate(
+    mut commands: Commands,
+    grid_query: Query<Ref<Grid>, With<Trees>>,
+    player_query: Query<Ref<Transform>, (With<Player>, Without<Goal>)>,
+    goal_query: Query<Ref<Transform>, (With<Goal>, Without<Player>)>,
+    validation: Option<Res<Validation>>,
+) {
+    let (Ok(grid), Ok(player), Ok(goal)) = (
+        grid_query.get_single(),
+        player_query.get_single(),
+        goal_query.get_single(),
+    ) else {
+        return;
+    };
+
+    if validation.is_some() && !grid.is_changed() && !player.is_changed() && !goal.is_changed() {
+        return;
+    }
+
+    commands.insert_resource(Validation::new(
+        &grid,
+        marker_cell(&player),
+        marker_cell(&goal),
+    ));
+}
+
+fn update_text(
+    validation: Option<Res<Validation>>,
+    mut text_query: Query<&mut Text, With<EditorText>>,
+) {
+    let Some(validation) = validation.filter(|validation| validation.is_changed()) else {
+        return;
+    };
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = validation.to_string();
+}
+
+#[allow(clippy::type_complexity)]
+fn save(
+    grid_query: Query<&Grid, With<Trees>>,
+    player_query: Query<&Transform, (With<Player>, Without<Goal>)>,
+    goal_query: Query<&Transform, (With<Goal>, Without<Player>)>,
+    level: Res<Level>,
+    validation: Option<Res<Validation>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::F6) {
+        return;
+    }
+
+    let (Ok(grid), Ok(player), Ok(goal)) = (
+        grid_query.get_single(),
+        player_query.get_single(),
+        goal_query.get_single(),
+    ) else {
+        return;
+    };
+
+    if !validation.is_some_and(|validation| validation.solvable) {
+        error!("Not saving the level, the goal can not be reached");
+        return;
+    }
+
+    // Markers are saved at the centre of their cell
+    let data = SaveData {
+        player: (marker_cell(player).as_vec2() + 0.5).into(),
+        goal: (marker_cell(goal).as_vec2() + 0.5).into(),
+        ..SaveData::new(grid, &level)
+    };
+
+    match data
+        .to_json()
+        .and_then(|json| Ok(std::fs::write(SAVE_PATH, json)?))
+    {
+        Ok(()) => info!("Saved the edited level to {}", SAVE_PATH),
+        Err(err) => error!("Could not save level: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::maze::{self, MazeType};
+
+    #[test]
+    fn test_edge_at() {
+        let grid = Grid::with_size(4, 3);
+        assert_eq!(
+            edge_at(&grid, Vec2::new(1.9, 0.5)),
+            Some((IVec2::new(1, 0), Dir::East))
+        );
+        assert_eq!(
+            edge_at(&grid, Vec2::new(2.5, 2.05)),
+            Some((IVec2::new(2, 2), Dir::South))
+        );
+        // The middle of a cell and the outer walls
+        assert_eq!(edge_at(&grid, Vec2::new(1.5, 1.5)), None);
+        assert_eq!(edge_at(&grid, Vec2::new(0.1, 1.5)), None);
+        assert_eq!(edge_at(&grid, Vec2::new(2.5, 2.95)), None);
+        assert_eq!(edge_at(&grid, Vec2::new(-0.5, 1.0)), None);
+    }
+
+    #[test]
+    fn test_stroke() {
+        let mut grid = Grid::with_size(4, 3);
+
+        // A click next to a wall toggles it
+        for open in [true, false] {
+            let stroke = Stroke::new(&grid, Vec2::new(1.9, 0.5));
+            stroke.release(&mut grid);
+            assert_eq!(grid.has_wall(IVec2::new(1, 0), Dir::East), !open);
+        }
+
+        // A drag carves a corridor, only between neighbours
+        let mut stroke = Stroke::new(&grid, Vec2::new(0.5, 0.5));
+        for point in [(0.6, 0.5), (1.5, 0.5), (1.5, 1.5), (3.5, 2.5)] {
+            stroke.drag(&mut grid, point.into());
+        }
+        stroke.release(&mut grid);
+        assert!(!grid.has_wall(IVec2::new(0, 0), Dir::East));
+        assert!(!grid.has_wall(IVec2::new(1, 0), Dir::North));
+        assert_eq!(grid.regions.num_sets(), 10);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut grid = maze::generate_grid(MazeType::Kruskal, 5, 4, &mut rng);
+        let (start, goal) = (IVec2::ZERO, IVec2::new(4, 3));
+
+        let validation = Validation::new(&grid, start, goal);
+        assert!(validation.solvable && validation.is_perfect());
+
+        // Closing any wall of a perfect maze disconnects it
+        let (pos, dir) = grid
+            .positions()
+            .flat_map(|pos| grid.open_dirs(pos).map(move |dir| (pos, dir)))
+            .next()
+            .unwrap();
+        grid.close_wall(pos, dir);
+        let validation = Validation::new(&grid, start, goal);
+        assert_eq!((validation.regions, validation.loops), (2, 0));
+
+        grid.open_wall(pos, dir);
+        let closed = grid
+            .positions()
+            .find_map(|pos| {
+                [Dir::North, Dir::East]
+                    .into_iter()
+                    .find(|dir| grid.is_inside(pos + IVec2::from(*dir)) && grid.has_wall(pos, *dir))
+                    .map(|dir| (pos, dir))
+            })
+            .unwrap();
+        grid.open_wall(closed.0, closed.1);
+        let validation = Validation::new(&grid, start, goal);
+        assert_eq!((validation.regions, validation.loops), (1, 1));
+        assert!(validation.solvable && !validation.is_perfect());
+
+        let validation = Validation::new(&Grid::with_size(5, 4), start, goal);
+        assert!(!validation.solvable);
+        assert_eq!(validation.regions, 20);
+    }
+}
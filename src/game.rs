@@ -0,0 +1,353 @@
+//! The player, the goal and the rules for finishing a level
+
+use bevy::prelude::*;
+
+use crate::{
+    components::*,
+    consts::*,
+    grid::{Dir, Grid},
+    states::GamePlayState,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ElapsedTime>()
+        .add_systems(OnEnter(GamePlayState::GeneratingMaze), reset_elapsed_time)
+        .add_systems(OnEnter(GamePlayState::Playing), setup_player_and_goal)
+        .add_systems(
+            Update,
+            (move_player, check_goal, update_elapsed_time).run_if(in_state(GamePlayState::Playing)),
+        );
+}
+
+fn collide(transform1: &Transform, transform2: &Transform) -> bool {
+    (transform1.translation.xy() - transform2.translation.xy())
+        .abs()
+        .cmplt((transform1.scale.xy() + transform2.scale.xy()) / 2.0)
+        .all()
+}
+
+fn check_goal(
+    mut ev_appexit: EventWriter<AppExit>,
+    player_query: Query<&Transform, (With<Player>, Without<Goal>)>,
+    goal_query: Query<&Transform, (Without<Player>, With<Goal>)>,
+    mut next_state: ResMut<NextState<GamePlayState>>,
+) {
+    let player_transform = player_query.single();
+    let goal_transform = goal_query.single();
+
+    if collide(player_transform, goal_transform) {
+        next_state.set(GamePlayState::LevelDone);
+        ev_appexit.send(AppExit::Success);
+    }
+}
+
+fn reset_elapsed_time(mut elapsed: ResMut<ElapsedTime>) {
+    elapsed.0 = 0.0;
+}
+
+fn update_elapsed_time(mut elapsed: ResMut<ElapsedTime>, time: Res<Time>) {
+    elapsed.0 += time.delta_seconds();
+}
+
+/// Spawn the player and the goal, unless they are still there from before
+/// the level was edited
+fn setup_player_and_goal(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_query: Query<(), With<Player>>,
+) {
+    if !player_query.is_empty() {
+        return;
+    }
+
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("elephant-square.png"),
+
+            transform: Transform {
+                translation: Vec3::new(
+                    (GRID_WIDTH - 1) as f32 + 0.5,
+                    (GRID_HEIGHT - 1) as f32 + 0.5,
+                    1.,
+                ),
+                scale: Vec3::new(1.0 / 192.0, 1.0 / 192.0, 1.0),
+                ..default()
+            },
+            ..default()
+        },
+        Goal,
+        Name::from("Goal"),
+    ));
+
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load("elephant-round.png"),
+            transform: Transform {
+                translation: Vec3::new(0.5, 0.5, 2.),
+                scale: Vec3::new(PLAYER_WIDTH / 192.0, PLAYER_HEIGHT / 192.0, 1.0),
+                ..default()
+            },
+            ..default()
+        },
+        Name::from("Player"),
+        Player,
+    ));
+}
+
+fn move_player(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+    tilemap_query: Query<&Grid, With<Trees>>,
+    time: Res<Time>,
+) {
+    let mut player_transform = player_query.single_mut();
+    let grid = tilemap_query.single();
+
+    let mut direction = Vec3::new(0., 0., 0.);
+
+    if keyboard_input.pressed(KeyCode::KeyW) || keyboard_input.pressed(KeyCode::ArrowUp) {
+        direction.y += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::KeyS) || keyboard_input.pressed(KeyCode::ArrowDown) {
+        direction.y -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::KeyA) || keyboard_input.pressed(KeyCode::ArrowLeft) {
+        direction.x -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::KeyD) || keyboard_input.pressed(KeyCode::ArrowRight) {
+        direction.x += 1.;
+    }
+
+    direction *= PLAYER_SPEED * time.delta_seconds();
+
+    if keyboard_input.pressed(KeyCode::ControlLeft) {
+        direction *= 4.0;
+    }
+
+    let pos = player_transform.translation.xy().floor();
+    let ipos = pos.as_ivec2();
+
+    let is_between = (player_transform.translation.xy() - (pos + Vec2::new(0.5, 0.5)))
+        .abs()
+        .cmpgt(Vec2::new(
+            (1.0 - PLAYER_WIDTH) / 2.0,
+            (1.0 - PLAYER_HEIGHT) / 2.0,
+        ));
+
+    let min_x = if is_between.y || grid.has_wall(ipos, Dir::West) {
+        pos.x
+    } else {
+        0.0
+    };
+    let max_x = if is_between.y || grid.has_wall(ipos, Dir::East) {
+        pos.x + 1.0
+    } else {
+        GRID_WIDTH as f32
+    };
+    let min_y = if is_between.x || grid.has_wall(ipos, Dir::South) {
+        pos.y
+    } else {
+        0.0
+    };
+    let max_y = if is_between.x || grid.has_wall(ipos, Dir::North) {
+        pos.y + 1.0
+    } else {
+        GRID_HEIGHT as f32
+    };
+
+    let d = Vec3::new(
+        PLAYER_WIDTH / 2.0 + PIXEL_WIDTH,
+        PLAYER_HEIGHT / 2.0 + PIXEL_HEIGHT,
+        0.0,
+    );
+
+    player_transform.translation = (player_transform.translation + direction).clamp(
+        Vec3::new(min_x, min_y, 0.) + d,
+        Vec3::new(max_x, max_y, 0.) - d,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+
+    use super::*;
+    use crate::{
+        editor,
+        maze::{self, GenerationMode, GenerationProgress, GenerationTask, MazeType},
+        states, tilemap,
+    };
+
+    /// The game without rendering, advancing the time by one generator step
+    /// every frame
+    fn app() -> App {
+        app_with_mode(GenerationMode::default())
+    }
+
+    fn app_with_mode(mode: GenerationMode) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            AssetPlugin::default(),
+            states::plugin,
+            maze::Plugin {
+                maze_type: MazeType::Kruskal,
+                braid: 0.0,
+                mode,
+                ..default()
+            },
+            plugin,
+        ))
+        .init_asset::<Image>()
+        .init_resource::<ButtonInput<KeyCode>>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            MAZE_GEN_TIME_MS,
+        )));
+        app.world_mut().spawn((Grid::new(), Trees));
+        app
+    }
+
+    fn state(app: &App) -> GamePlayState {
+        app.world().resource::<State<GamePlayState>>().get().clone()
+    }
+
+    /// Run frames until the maze is generated
+    fn generate(app: &mut App) {
+        for _ in 0..4 * GRID_WIDTH * GRID_HEIGHT {
+            app.update();
+            if state(app) != GamePlayState::GeneratingMaze {
+                break;
+            }
+        }
+        assert_eq!(state(app), GamePlayState::Playing);
+    }
+
+    #[test]
+    fn test_generating() {
+        let mut app = app();
+        app.update();
+        assert_eq!(state(&app), GamePlayState::GeneratingMaze);
+        assert!(app.world().get_resource::<maze::Level>().is_some());
+
+        generate(&mut app);
+        let world = app.world_mut();
+        let grid = world.query_filtered::<&Grid, With<Trees>>().single(world);
+        assert_eq!(grid.regions.num_sets(), 1);
+    }
+
+    #[test]
+    fn test_instant() {
+        let mut app = app_with_mode(GenerationMode::Instant);
+        app.update();
+        app.update();
+        assert_eq!(state(&app), GamePlayState::Playing);
+        assert_eq!(app.world().resource::<GenerationProgress>().0, 1.0);
+    }
+
+    #[test]
+    fn test_background() {
+        let mut app = app_with_mode(GenerationMode::Background { snapshot_steps: 4 });
+        for _ in 0..1000 {
+            app.update();
+            if state(&app) != GamePlayState::GeneratingMaze {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(state(&app), GamePlayState::Playing);
+
+        let world = app.world_mut();
+        let grid = world.query_filtered::<&Grid, With<Trees>>().single(world);
+        assert_eq!(grid.regions.num_sets(), 1);
+        assert_eq!(world.query::<&GenerationTask>().iter(world).count(), 0);
+    }
+
+    #[test]
+    fn test_spawn() {
+        let mut app = app();
+        generate(&mut app);
+        app.update();
+
+        let world = app.world_mut();
+        assert_eq!(world.query::<&Player>().iter(world).count(), 1);
+        assert_eq!(world.query::<&Goal>().iter(world).count(), 1);
+    }
+
+    #[test]
+    fn test_goal() {
+        let mut app = app();
+        generate(&mut app);
+        app.update();
+        assert_eq!(state(&app), GamePlayState::Playing);
+        assert!(app.world().resource::<ElapsedTime>().0 > 0.0);
+
+        let world = app.world_mut();
+        let goal = *world
+            .query_filtered::<&Transform, With<Goal>>()
+            .single(world);
+        world
+            .query_filtered::<&mut Transform, With<Player>>()
+            .single_mut(world)
+            .translation = goal.translation;
+
+        app.update();
+        app.update();
+        assert_eq!(state(&app), GamePlayState::LevelDone);
+    }
+
+    fn press(app: &mut App, key: KeyCode) {
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(key);
+        app.update();
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .reset_all();
+        app.update();
+    }
+
+    #[test]
+    fn test_editor() {
+        let mut app = app();
+        app.add_plugins(editor::plugin)
+            .init_resource::<ButtonInput<MouseButton>>()
+            .add_event::<tilemap::TileClick>();
+        generate(&mut app);
+        app.update();
+
+        press(&mut app, KeyCode::F4);
+        assert_eq!(state(&app), GamePlayState::Editing);
+        let validation = *app.world().resource::<editor::Validation>();
+        assert!(validation.solvable && validation.is_perfect());
+
+        // Walling in the goal is noticed
+        let world = app.world_mut();
+        let goal = world
+            .query_filtered::<&Transform, With<Goal>>()
+            .single(world)
+            .translation
+            .xy()
+            .floor()
+            .as_ivec2();
+        let mut grid = world
+            .query_filtered::<&mut Grid, With<Trees>>()
+            .single_mut(world);
+        for dir in Dir::ALL {
+            if grid.is_inside(goal + IVec2::from(dir)) {
+                grid.close_wall(goal, dir);
+            }
+        }
+        app.update();
+        assert!(!app.world().resource::<editor::Validation>().solvable);
+
+        // Returning to the game keeps the player where it was
+        press(&mut app, KeyCode::F4);
+        assert_eq!(state(&app), GamePlayState::Playing);
+        let world = app.world_mut();
+        assert_eq!(world.query::<&Player>().iter(world).count(), 1);
+        assert_eq!(world.query::<&Goal>().iter(world).count(), 1);
+    }
+}
@@ -1,13 +1,37 @@
-use crate::{consts::*, disjoint_set::DisjointSet, tilemap};
+use crate::{
+    consts::*,
+    disjoint_set::{self, DisjointSet, UndoableDisjointSet, UnionFind},
+    tilemap,
+};
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Component)]
-pub struct Grid {
+/// The walls of a maze together with the regions of connected cells. The
+/// regions can use any [`UnionFind`]; with an [`UndoableDisjointSet`] the
+/// walls which were opened are recorded as well, see [`Grid::rollback`].
+#[derive(Component, Clone)]
+pub struct Grid<R: UnionFind = DisjointSet> {
+    width: usize,
+    height: usize,
     data: Vec<u8>,
-    pub regions: DisjointSet,
+    pub regions: R,
+    /// The index and previous bits of every cell changed by `open_wall`, only
+    /// recorded when the regions are undoable
+    wall_log: Vec<(usize, u8)>,
+    /// Every wall opened since `start_recording`
+    recorded: Option<Vec<(IVec2, Dir)>>,
+    /// The cells changed since the tilemap was last uploaded
+    dirty: tilemap::DirtyRect,
 }
 
+/// A state of an undoable grid which can be restored with [`Grid::rollback`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridCheckpoint {
+    regions: disjoint_set::Checkpoint,
+    walls: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Dir {
     North = 0b0001,
     East = 0b0010,
@@ -16,6 +40,8 @@ pub enum Dir {
 }
 
 impl Dir {
+    pub const ALL: [Dir; 4] = [Dir::North, Dir::East, Dir::South, Dir::West];
+
     pub fn reverse(&self) -> Self {
         match self {
             Dir::North => Dir::South,
@@ -37,26 +63,153 @@ impl From<Dir> for IVec2 {
     }
 }
 
-impl tilemap::TilemapData for Grid {
-    fn data(&self) -> &Vec<u8> {
+impl<R: UnionFind> tilemap::TilemapData for Grid<R> {
+    type Item = u8;
+
+    fn data(&self) -> &[u8] {
         &self.data
     }
 
     fn size(&self) -> Vec4 {
-        Vec4::new(GRID_WIDTH as f32, GRID_HEIGHT as f32, 0.0, 0.0)
+        Vec4::new(self.width as f32, self.height as f32, 0.0, 0.0)
+    }
+
+    fn dirty(&self) -> Option<bevy::math::URect> {
+        self.dirty.get()
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Grid {
     pub fn new() -> Self {
+        Self::with_size(GRID_WIDTH, GRID_HEIGHT)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Self {
+        Self::with_regions(width, height)
+    }
+
+    /// Construct a grid from the wall bits returned by `walls`, reconstructing
+    /// the regions
+    pub fn from_walls(width: usize, height: usize, walls: &[u8]) -> Self {
+        let mut grid = Self::with_size(width, height);
+        for (index, bits) in walls.iter().enumerate() {
+            let pos = grid.position(index);
+            for dir in [Dir::North, Dir::East] {
+                if bits & dir as u8 != 0 && grid.is_inside(pos + IVec2::from(dir)) {
+                    grid.open_wall(pos, dir);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Put a wall back. Regions can not be split, so they are rebuilt from
+    /// the walls. Walls on the border of the grid are always closed, so they
+    /// are ignored.
+    pub fn close_wall(&mut self, pos: IVec2, dir: Dir) {
+        let new_pos: IVec2 = pos + IVec2::from(dir);
+        if !self.is_inside(pos) || !self.is_inside(new_pos) {
+            return;
+        }
+        *self.get_walls_mut(pos) &= !(dir as u8);
+        *self.get_walls_mut(new_pos) &= !(dir.reverse() as u8);
+        self.rebuild_regions();
+    }
+
+    /// Open the wall if it is closed and close it otherwise. Returns whether
+    /// the wall is open afterwards. Walls on the border of the grid can not
+    /// be opened and are left closed.
+    pub fn toggle_wall(&mut self, pos: IVec2, dir: Dir) -> bool {
+        if !self.is_inside(pos) || !self.is_inside(pos + IVec2::from(dir)) {
+            return false;
+        }
+        if self.has_wall(pos, dir) {
+            self.open_wall(pos, dir);
+            true
+        } else {
+            self.close_wall(pos, dir);
+            false
+        }
+    }
+
+    fn rebuild_regions(&mut self) {
+        self.regions = DisjointSet::new(self.num_cells());
+        for pos in self.positions().collect::<Vec<_>>() {
+            for dir in [Dir::North, Dir::East] {
+                if !self.has_wall(pos, dir) {
+                    self.join_regions(pos, pos + IVec2::from(dir));
+                }
+            }
+        }
+    }
+
+    /// Copy the walls and regions of a grid of the same size using other
+    /// regions. Only the cells whose walls differ are marked dirty.
+    pub fn copy_from<S: UnionFind>(&mut self, other: &Grid<S>) {
+        assert_eq!(self.num_cells(), other.num_cells());
+        for (index, bits) in other.walls().iter().enumerate() {
+            if self.data[index] != *bits {
+                self.data[index] = *bits;
+                self.dirty.add_tile(self.position(index).as_uvec2());
+            }
+        }
+        self.regions = DisjointSet::new(self.num_cells());
+        for index in 0..self.num_cells() {
+            self.regions.join(index, other.regions.find(index));
+        }
+    }
+}
+
+impl<R: UnionFind> Grid<R> {
+    /// An empty grid using `R` for its regions
+    pub fn with_regions(width: usize, height: usize) -> Self {
         Self {
-            data: vec![0; GRID_WIDTH * GRID_HEIGHT],
-            regions: DisjointSet::new(GRID_WIDTH * GRID_HEIGHT),
+            width,
+            height,
+            data: vec![0; width * height],
+            regions: R::new(width * height),
+            wall_log: Vec::new(),
+            recorded: None,
+            dirty: tilemap::DirtyRect::full(UVec2::new(width as u32, height as u32)),
         }
     }
 
-    fn index(&self, pos: IVec2) -> usize {
-        (pos.y as usize) * GRID_WIDTH + pos.x as usize
+    pub fn walls(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn index(&self, pos: IVec2) -> usize {
+        (pos.y as usize) * self.width + pos.x as usize
+    }
+
+    pub fn position(&self, index: usize) -> IVec2 {
+        IVec2::new((index % self.width) as i32, (index / self.width) as i32)
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = IVec2> + '_ {
+        (0..self.num_cells()).map(|i| self.position(i))
     }
 
     pub fn region(&self, pos: IVec2) -> usize {
@@ -73,6 +226,7 @@ impl Grid {
 
     pub fn get_walls_mut(&mut self, pos: IVec2) -> &mut u8 {
         let index = self.index(pos);
+        self.dirty.add_tile(pos.as_uvec2());
         &mut self.data[index]
     }
 
@@ -80,33 +234,76 @@ impl Grid {
         !self.regions.is_singleton(self.index(pos))
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn remove_wall(&mut self, pos: IVec2, dir: Dir) -> Result<(), ()> {
         let new_pos: IVec2 = pos + IVec2::from(dir);
         if self.region(pos) == self.region(new_pos) {
             return Err(());
         }
+        self.open_wall(pos, dir);
+
+        Ok(())
+    }
+
+    /// Remove a wall even if this creates a loop. Both cells have to be
+    /// inside the grid.
+    pub fn open_wall(&mut self, pos: IVec2, dir: Dir) {
+        let new_pos: IVec2 = pos + IVec2::from(dir);
+        debug_assert!(
+            self.is_inside(pos) && self.is_inside(new_pos),
+            "the wall {dir:?} of {pos} is on the border"
+        );
         self.join_regions(pos, new_pos);
 
+        if R::UNDOABLE {
+            for p in [pos, new_pos] {
+                let index = self.index(p);
+                self.wall_log.push((index, self.data[index]));
+            }
+        }
+
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push((pos, dir));
+        }
+
         *self.get_walls_mut(pos) |= dir as u8;
         *self.get_walls_mut(new_pos) |= dir.reverse() as u8;
+    }
 
-        Ok(())
+    /// Remember the walls opened from now on, until they are taken with
+    /// `take_recorded`. Does nothing if the grid is already recording.
+    pub fn start_recording(&mut self) {
+        self.recorded.get_or_insert_with(Vec::new);
+    }
+
+    /// The walls opened since the last call, in order
+    pub fn take_recorded(&mut self) -> Vec<(IVec2, Dir)> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
     }
 
     pub fn has_wall(&self, pos: IVec2, dir: Dir) -> bool {
         (self.get_walls(pos) & (dir as u8) == 0)
             || (pos.x == 0 && dir == Dir::West)
             || (pos.y == 0 && dir == Dir::South)
-            || (pos.x == GRID_WIDTH as i32 - 1 && dir == Dir::East)
-            || (pos.y == GRID_HEIGHT as i32 - 1 && dir == Dir::North)
+            || (pos.x == self.width as i32 - 1 && dir == Dir::East)
+            || (pos.y == self.height as i32 - 1 && dir == Dir::North)
     }
 
-    fn is_inside(&self, pos: IVec2) -> bool {
-        pos.x >= 0 && pos.x < GRID_WIDTH as i32 && pos.y >= 0 && pos.y < GRID_HEIGHT as i32
+    pub fn open_dirs(&self, pos: IVec2) -> impl Iterator<Item = Dir> + '_ {
+        Dir::ALL
+            .into_iter()
+            .filter(move |d| !self.has_wall(pos, *d))
+    }
+
+    pub fn is_inside(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.x < self.width as i32 && pos.y >= 0 && pos.y < self.height as i32
     }
 
     pub fn possible_moves(&self, pos: IVec2) -> Vec<Dir> {
-        [Dir::North, Dir::East, Dir::South, Dir::West]
+        Dir::ALL
             .into_iter()
             .filter(|d| {
                 let p = pos + IVec2::from(*d);
@@ -115,3 +312,95 @@ impl Grid {
             .collect()
     }
 }
+
+impl Grid<UndoableDisjointSet> {
+    /// Remember the current walls and regions so that they can be restored
+    /// with `rollback`
+    pub fn checkpoint(&self) -> GridCheckpoint {
+        GridCheckpoint {
+            regions: self.regions.checkpoint(),
+            walls: self.wall_log.len(),
+        }
+    }
+
+    /// Close every wall opened since `checkpoint` was taken and split the
+    /// regions they joined. Walls changed through `get_walls_mut` are not
+    /// restored.
+    pub fn rollback(&mut self, checkpoint: GridCheckpoint) {
+        self.regions.rollback(checkpoint.regions);
+        for (index, bits) in self.wall_log.drain(checkpoint.walls..).rev() {
+            self.data[index] = bits;
+            let pos = IVec2::new((index % self.width) as i32, (index / self.width) as i32);
+            self.dirty.add_tile(pos.as_uvec2());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut grid = Grid::<UndoableDisjointSet>::with_regions(3, 2);
+        grid.open_wall(IVec2::new(0, 0), Dir::East);
+        let walls = grid.walls().to_vec();
+        let checkpoint = grid.checkpoint();
+
+        grid.open_wall(IVec2::new(1, 0), Dir::North);
+        assert!(grid.remove_wall(IVec2::new(1, 1), Dir::East).is_ok());
+        grid.open_wall(IVec2::new(1, 0), Dir::North);
+        assert_eq!(grid.regions.num_sets(), 3);
+
+        grid.rollback(checkpoint);
+        assert_eq!(grid.walls(), &walls[..]);
+        assert_eq!(grid.regions.num_sets(), 5);
+        assert_eq!(grid.region(IVec2::new(0, 0)), grid.region(IVec2::new(1, 0)));
+        assert!(!grid.is_visited(IVec2::new(1, 1)));
+        assert!(grid.has_wall(IVec2::new(1, 0), Dir::North));
+    }
+
+    #[test]
+    fn test_close_wall() {
+        let mut grid = Grid::with_size(3, 1);
+        grid.open_wall(IVec2::new(0, 0), Dir::East);
+        grid.open_wall(IVec2::new(1, 0), Dir::East);
+        assert_eq!(grid.regions.num_sets(), 1);
+
+        grid.close_wall(IVec2::new(2, 0), Dir::West);
+        assert!(grid.has_wall(IVec2::new(1, 0), Dir::East));
+        assert_eq!(grid.get_walls(IVec2::new(2, 0)), 0);
+        assert_eq!(grid.regions.num_sets(), 2);
+        assert_ne!(grid.region(IVec2::new(1, 0)), grid.region(IVec2::new(2, 0)));
+
+        assert!(grid.toggle_wall(IVec2::new(1, 0), Dir::East));
+        assert_eq!(grid.regions.num_sets(), 1);
+        assert!(!grid.toggle_wall(IVec2::new(0, 0), Dir::East));
+        assert_eq!(grid.region(IVec2::new(1, 0)), grid.region(IVec2::new(2, 0)));
+        assert_ne!(grid.region(IVec2::new(0, 0)), grid.region(IVec2::new(1, 0)));
+
+        // Border walls stay closed and nothing outside the grid is touched
+        let walls = grid.walls().to_vec();
+        assert!(!grid.toggle_wall(IVec2::new(0, 0), Dir::West));
+        assert!(!grid.toggle_wall(IVec2::new(2, 0), Dir::East));
+        assert!(!grid.toggle_wall(IVec2::new(1, 0), Dir::South));
+        grid.close_wall(IVec2::new(0, 0), Dir::North);
+        assert_eq!(grid.walls(), &walls[..]);
+    }
+
+    #[test]
+    fn test_dirty() {
+        use crate::tilemap::TilemapData;
+        use bevy::math::URect;
+
+        let mut grid = Grid::with_size(4, 4);
+        assert_eq!(grid.dirty(), Some(URect::new(0, 0, 4, 4)));
+        grid.clear_dirty();
+
+        grid.open_wall(IVec2::new(1, 2), Dir::North);
+        assert_eq!(grid.dirty(), Some(URect::new(1, 2, 2, 4)));
+        grid.clear_dirty();
+        grid.close_wall(IVec2::new(2, 0), Dir::West);
+        assert_eq!(grid.dirty(), Some(URect::new(1, 0, 3, 1)));
+    }
+}
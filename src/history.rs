@@ -0,0 +1,547 @@
+//! Recording of the walls removed while generating a maze, so that the
+//! generation can be replayed forwards and backwards, scrubbed through and
+//! exported.
+//!
+//! While playing, F7 starts or stops the replay, Space pauses it, R reverses
+//! it, `-` and `=` change its speed and `[` and `]` step through it one wall
+//! at a time. The timeline at the bottom of the screen can be dragged with the
+//! mouse. F8 exports the history as JSON.
+
+use bevy::{prelude::*, ui::RelativeCursorPosition};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::*,
+    consts::*,
+    disjoint_set::{UndoableDisjointSet, UnionFind},
+    grid::{Dir, Grid, GridCheckpoint},
+    states::GamePlayState,
+};
+
+const JSON_PATH: &str = "history.json";
+
+/// How long a replay at the default speed takes, in seconds
+const REPLAY_TIME: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WallRemoval {
+    /// The generator step which removed the wall
+    pub step: usize,
+    /// The generator cursor which removed the wall, for generators which use
+    /// cursors
+    pub cursor: Option<usize>,
+    pub x: i32,
+    pub y: i32,
+    pub dir: Dir,
+}
+
+impl WallRemoval {
+    pub fn pos(&self) -> IVec2 {
+        IVec2::new(self.x, self.y)
+    }
+}
+
+/// Every wall removed while generating the current maze, in order
+#[derive(Resource, Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct History {
+    pub width: usize,
+    pub height: usize,
+    /// The number of generator steps recorded so far
+    pub steps: usize,
+    pub removals: Vec<WallRemoval>,
+}
+
+/// A replay of the history which is in progress
+#[derive(Resource, Clone)]
+pub struct Replay {
+    /// The number of removals applied to the grid
+    pub position: usize,
+    /// Removals per second, negative to replay backwards
+    pub speed: f32,
+    pub paused: bool,
+    carry: f32,
+    /// The position the grid currently shows
+    shown: usize,
+    /// The grid at some position, with the checkpoint taken before every
+    /// removal applied to it, so that seeking backwards rolls the removals
+    /// back instead of replaying the history from the start
+    undoable: Grid<UndoableDisjointSet>,
+    checkpoints: Vec<GridCheckpoint>,
+}
+
+#[derive(Component)]
+struct Timeline;
+
+#[derive(Component)]
+struct TimelineFill;
+
+#[derive(Component)]
+struct TimelineText;
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<History>()
+        .add_systems(
+            Update,
+            (
+                (toggle_replay, export),
+                (control_replay, scrub, advance, apply)
+                    .chain()
+                    .run_if(resource_exists::<Replay>),
+            )
+                .chain()
+                .run_if(in_state(GamePlayState::Playing)),
+        )
+        .add_systems(
+            Update,
+            update_timeline.run_if(resource_changed_or_removed::<Replay>()),
+        )
+        .add_systems(OnExit(GamePlayState::Playing), stop_replay);
+}
+
+impl History {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            ..default()
+        }
+    }
+
+    /// Record the walls opened on `grid` since the last call as one step.
+    /// `cursors` are the cursors of the generator after the step, a wall is
+    /// attributed to the cursor which moved through it onto the next cell.
+    pub fn record(&mut self, grid: &mut Grid, cursors: &[Option<IVec2>]) {
+        for (pos, dir) in grid.take_recorded() {
+            let next = pos + IVec2::from(dir);
+            self.removals.push(WallRemoval {
+                step: self.steps,
+                cursor: cursors.iter().position(|cursor| *cursor == Some(next)),
+                x: pos.x,
+                y: pos.y,
+                dir,
+            });
+        }
+        self.steps += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.removals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removals.is_empty()
+    }
+
+    /// The grid after the first `position` removals
+    pub fn grid_at(&self, position: usize) -> Grid {
+        let mut grid = Grid::with_size(self.width, self.height);
+        self.seek(&mut grid, 0, position);
+        grid
+    }
+
+    /// Change a grid showing the first `from` removals to show the first `to`
+    /// removals. Walls cannot be closed again, so only seeking forwards is
+    /// possible, see `Replay` for seeking backwards.
+    pub fn seek<R: UnionFind>(&self, grid: &mut Grid<R>, from: usize, to: usize) {
+        let to = to.min(self.len());
+        for removal in self.removals.get(from..to).unwrap_or_default() {
+            grid.open_wall(removal.pos(), removal.dir);
+        }
+    }
+
+    /// Whether replaying the history leads to `grid`, which is no longer the
+    /// case once the maze was edited or another one was loaded
+    pub fn matches(&self, grid: &Grid) -> bool {
+        grid.width() == self.width
+            && grid.height() == self.height
+            && self.grid_at(self.len()).walls() == grid.walls()
+    }
+
+    /// Forget the recorded removals, once the maze was changed in another way
+    pub fn clear(&mut self) {
+        self.steps = 0;
+        self.removals.clear();
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+impl Replay {
+    /// A replay of `history` starting from the grid after all its removals
+    pub fn new(history: &History) -> Self {
+        let mut replay = Self {
+            position: 0,
+            speed: (history.len() as f32 / REPLAY_TIME).max(1.0),
+            paused: false,
+            carry: 0.0,
+            shown: history.len(),
+            undoable: Grid::with_regions(history.width, history.height),
+            checkpoints: Vec::with_capacity(history.len()),
+        };
+        replay.seek_undoable(history, history.len());
+        replay
+    }
+
+    /// Move the undoable grid to the first `to` removals
+    fn seek_undoable(&mut self, history: &History, to: usize) {
+        let to = to.min(history.len());
+        if let Some(&checkpoint) = self.checkpoints.get(to) {
+            self.undoable.rollback(checkpoint);
+            self.checkpoints.truncate(to);
+        }
+        for removal in &history.removals[self.checkpoints.len()..to] {
+            self.checkpoints.push(self.undoable.checkpoint());
+            self.undoable.open_wall(removal.pos(), removal.dir);
+        }
+    }
+
+    /// Change `grid` to show the first `position` removals. Going forwards
+    /// opens the walls on `grid`, going backwards copies the rolled back
+    /// undoable grid into it.
+    fn show(&mut self, history: &History, grid: &mut Grid) {
+        let to = self.position.min(history.len());
+        self.seek_undoable(history, to);
+        if to < self.shown {
+            grid.copy_from(&self.undoable);
+        } else {
+            history.seek(grid, self.shown, to);
+        }
+        self.shown = to;
+    }
+
+    /// Move by `speed` removals per second for `dt` seconds
+    fn advance(&mut self, dt: f32, len: usize) {
+        self.carry += self.speed * dt;
+        let steps = self.carry.trunc();
+        self.carry -= steps;
+        self.position = (self.position as i64 + steps as i64).clamp(0, len as i64) as usize;
+    }
+}
+
+fn toggle_replay(
+    mut commands: Commands,
+    history: Res<History>,
+    replay: Option<ResMut<Replay>>,
+    mut grid_query: Query<&mut Grid, With<Trees>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::F7) || history.is_empty() {
+        return;
+    }
+
+    let Ok(mut grid) = grid_query.get_single_mut() else {
+        return;
+    };
+
+    if let Some(mut replay) = replay {
+        replay.position = history.len();
+        replay.show(&history, &mut grid);
+        commands.remove_resource::<Replay>();
+    } else if history.matches(&grid) {
+        commands.insert_resource(Replay::new(&history));
+        spawn_timeline(&mut commands);
+    } else {
+        warn!("The maze was changed after it was generated, so it can not be replayed");
+    }
+}
+
+fn stop_replay(
+    mut commands: Commands,
+    history: Res<History>,
+    replay: Option<ResMut<Replay>>,
+    mut grid_query: Query<&mut Grid, With<Trees>>,
+) {
+    let Some(mut replay) = replay else {
+        return;
+    };
+    if let Ok(mut grid) = grid_query.get_single_mut() {
+        replay.position = history.len();
+        replay.show(&history, &mut grid);
+    }
+    commands.remove_resource::<Replay>();
+}
+
+fn control_replay(
+    mut replay: ResMut<Replay>,
+    history: Res<History>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Space) {
+        replay.paused = !replay.paused;
+    }
+    if input.just_pressed(KeyCode::KeyR) {
+        replay.speed = -replay.speed;
+    }
+    if input.just_pressed(KeyCode::Minus) {
+        replay.speed /= 2.0;
+    }
+    if input.just_pressed(KeyCode::Equal) {
+        replay.speed *= 2.0;
+    }
+    if input.just_pressed(KeyCode::BracketLeft) {
+        replay.paused = true;
+        replay.position = replay.position.saturating_sub(1);
+    }
+    if input.just_pressed(KeyCode::BracketRight) {
+        replay.paused = true;
+        replay.position = (replay.position + 1).min(history.len());
+    }
+}
+
+fn scrub(
+    mut replay: ResMut<Replay>,
+    history: Res<History>,
+    timeline_query: Query<(&Interaction, &RelativeCursorPosition), With<Timeline>>,
+) {
+    let Ok((interaction, cursor)) = timeline_query.get_single() else {
+        return;
+    };
+
+    if *interaction != Interaction::Pressed {
+        return;
+    }
+
+    if let Some(normalized) = cursor.normalized {
+        replay.position = (normalized.x.clamp(0.0, 1.0) * history.len() as f32).round() as usize;
+        replay.carry = 0.0;
+    }
+}
+
+fn advance(mut replay: ResMut<Replay>, history: Res<History>, time: Res<Time>) {
+    if replay.paused {
+        return;
+    }
+    replay.advance(time.delta_seconds(), history.len());
+}
+
+fn apply(
+    mut replay: ResMut<Replay>,
+    history: Res<History>,
+    mut grid_query: Query<&mut Grid, With<Trees>>,
+) {
+    if replay.position == replay.shown {
+        return;
+    }
+
+    let Ok(mut grid) = grid_query.get_single_mut() else {
+        return;
+    };
+
+    replay.show(&history, &mut grid);
+}
+
+fn spawn_timeline(commands: &mut Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(MARGIN),
+                    left: Val::Px(MARGIN),
+                    right: Val::Px(MARGIN),
+                    height: Val::Px(MARGIN),
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.5).into(),
+                ..default()
+            },
+            Interaction::default(),
+            RelativeCursorPosition::default(),
+            Timeline,
+            Name::from("Timeline"),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: Color::WHITE.into(),
+                    ..default()
+                },
+                TimelineFill,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(MARGIN),
+                    ..default()
+                }),
+                TimelineText,
+            ));
+        });
+}
+
+fn update_timeline(
+    mut commands: Commands,
+    replay: Option<Res<Replay>>,
+    history: Res<History>,
+    timeline_query: Query<Entity, With<Timeline>>,
+    mut fill_query: Query<&mut Style, With<TimelineFill>>,
+    mut text_query: Query<&mut Text, With<TimelineText>>,
+) {
+    let Some(replay) = replay else {
+        for entity in timeline_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+
+    if let Ok(mut style) = fill_query.get_single_mut() {
+        style.width = Val::Percent(100.0 * replay.position as f32 / history.len().max(1) as f32);
+    }
+
+    if let Ok(mut text) = text_query.get_single_mut() {
+        let step = replay
+            .position
+            .checked_sub(1)
+            .map_or(0, |i| history.removals[i].step + 1);
+        text.sections[0].value = format!(
+            "Replay {}/{} walls, step {}/{}, {:.1} walls/s{}",
+            replay.position,
+            history.len(),
+            step,
+            history.steps,
+            replay.speed,
+            if replay.paused { " (paused)" } else { "" }
+        );
+    }
+}
+
+fn export(history: Res<History>, input: Res<ButtonInput<KeyCode>>) {
+    if !input.just_pressed(KeyCode::F8) {
+        return;
+    }
+
+    match history
+        .to_json()
+        .map_err(|err| err.to_string())
+        .and_then(|json| std::fs::write(JSON_PATH, json).map_err(|err| err.to_string()))
+    {
+        Ok(()) => info!("Exported generation history to {}", JSON_PATH),
+        Err(err) => error!("Could not export generation history: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::maze::{CursorConfig, MazeState, MazeType};
+
+    fn record(maze_type: MazeType) -> (Grid, History) {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut grid = Grid::with_size(6, 5);
+        let mut state = MazeState::new(maze_type, 6, 5, &mut rng);
+        let mut history = History::new(6, 5);
+        grid.start_recording();
+        while grid.regions.num_sets() > 1 {
+            state.step(&mut grid, &mut rng);
+            history.record(&mut grid, &state.cursors());
+        }
+        (grid, history)
+    }
+
+    #[test]
+    fn test_record() {
+        let (grid, history) = record(MazeType::Kruskal);
+        assert_eq!(history.len(), 29);
+        assert_eq!(history.steps, 29);
+        assert!(history.removals.iter().all(|r| r.cursor.is_none()));
+        assert_eq!(history.grid_at(history.len()).walls(), grid.walls());
+
+        let (grid, history) = record(MazeType::Backtracking);
+        assert_eq!(history.len(), 29);
+        assert!(history.removals.last().unwrap().step < history.steps);
+        assert!(history
+            .removals
+            .iter()
+            .all(|r| r.cursor.is_some_and(|c| c < CursorConfig::default().count)));
+        assert!(history.removals.windows(2).all(|w| w[0].step <= w[1].step));
+        assert_eq!(history.grid_at(history.len()).walls(), grid.walls());
+    }
+
+    #[test]
+    fn test_seek() {
+        let (_, history) = record(MazeType::Wfc);
+        let mut grid = history.grid_at(10);
+        assert_eq!(grid.regions.num_sets(), 20);
+
+        history.seek(&mut grid, 10, 25);
+        assert_eq!(grid.walls(), history.grid_at(25).walls());
+        history.seek(&mut grid, 25, 100);
+        assert_eq!(grid.regions.num_sets(), 1);
+    }
+
+    #[test]
+    fn test_show() {
+        let (mut grid, history) = record(MazeType::Wfc);
+        let mut replay = Replay::new(&history);
+
+        for position in [20, 5, 0, 12, 29, 3] {
+            replay.position = position;
+            replay.show(&history, &mut grid);
+            let expected = history.grid_at(position);
+            assert_eq!(grid.walls(), expected.walls());
+            assert_eq!(grid.regions.num_sets(), expected.regions.num_sets());
+            assert_eq!(replay.checkpoints.len(), position);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let (mut grid, mut history) = record(MazeType::Kruskal);
+        assert!(history.matches(&grid));
+
+        let removal = history.removals[0];
+        grid.close_wall(removal.pos(), removal.dir);
+        assert!(!history.matches(&grid));
+
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(history.steps, 0);
+    }
+
+    #[test]
+    fn test_replay() {
+        let (_, history) = record(MazeType::Kruskal);
+        let mut replay = Replay::new(&history);
+        assert_eq!(replay.speed, 2.9);
+
+        replay.advance(1.0, history.len());
+        assert_eq!(replay.position, 2);
+        replay.advance(1.0, history.len());
+        assert_eq!(replay.position, 5);
+
+        replay.speed = -100.0;
+        replay.advance(1.0, history.len());
+        assert_eq!(replay.position, 0);
+        replay.speed = 100.0;
+        replay.advance(1.0, history.len());
+        assert_eq!(replay.position, history.len());
+    }
+
+    #[test]
+    fn test_json() {
+        let (_, history) = record(MazeType::Backtracking);
+        let json = history.to_json().unwrap();
+        assert_eq!(History::from_json(&json).unwrap(), history);
+    }
+}
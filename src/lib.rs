@@ -0,0 +1,20 @@
+pub mod ascii;
+pub mod bias;
+pub mod components;
+pub mod consts;
+pub mod cover;
+pub mod cursors;
+pub mod disjoint_set;
+pub mod editor;
+pub mod game;
+pub mod grid;
+pub mod history;
+pub mod maze;
+pub mod overlay;
+pub mod print;
+pub mod save;
+pub mod solver;
+pub mod states;
+pub mod stats;
+pub mod tilemap;
+pub mod tileset_builder;
@@ -6,30 +6,68 @@ use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::window::PresentMode;
 #[cfg(not(target_arch = "wasm32"))]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use grid::{Dir, Grid};
-use maze::MazeType;
+use maze::grid::Grid;
+use maze::maze::MazeType;
+use maze::states;
+use maze::tilemap::{self, Tilemap};
+use maze::{
+    ascii, bias, cover, cursors, editor, game, history, overlay, print, save, stats,
+    tileset_builder,
+};
 use rand::Rng;
-use states::GamePlayState;
-use tilemap::Tilemap;
 
-mod components;
-mod consts;
-mod disjoint_set;
-mod grid;
-mod maze;
-mod overlay;
-mod states;
-mod tilemap;
-mod tileset_builder;
-
-use crate::components::*;
-use crate::consts::*;
+use maze::components::*;
+use maze::consts::*;
 
 // Background color: b28d70
 
 fn main() {
     let mut app = App::new();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match &args[..] {
+        [flag, num_seeds] if flag == "--batch" => {
+            let num_seeds = num_seeds
+                .parse()
+                .expect("The number of seeds should be an integer");
+            stats::run_batch(num_seeds);
+            return;
+        }
+        [flag, samples] if flag == "--bias" => {
+            let samples = samples
+                .parse()
+                .expect("The number of samples should be an integer");
+            bias::run_bias(samples);
+            return;
+        }
+        [flag, path] if flag == "--load" => {
+            let grid = match std::fs::read_to_string(path) {
+                Ok(text) => text.parse::<Grid>().map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            }
+            .and_then(|grid| {
+                if grid.width() == GRID_WIDTH && grid.height() == GRID_HEIGHT {
+                    Ok(grid)
+                } else {
+                    Err(format!("the maze should be {GRID_WIDTH}x{GRID_HEIGHT}"))
+                }
+            })
+            .unwrap_or_else(|err| {
+                eprintln!("Could not load the maze {}: {}", path, err);
+                std::process::exit(1);
+            });
+            app.insert_resource(LoadedGrid(grid));
+        }
+        [flag, path] if flag == "--level" => {
+            let data = save::SaveData::load(path).unwrap_or_else(|err| {
+                eprintln!("Could not load the level {}: {}", path, err);
+                std::process::exit(1);
+            });
+            app.insert_resource(save::PendingLoad(data));
+        }
+        _ => {}
+    }
+
     let present_mode = if cfg!(target_arch = "wasm32") {
         PresentMode::default()
     } else {
@@ -50,21 +88,26 @@ fn main() {
         }),
         tilemap::plugin,
         tilemap::register_data::<tilemap::TilemapShader, Grid>,
+        tilemap::register_picking::<Grid>,
         overlay::plugin,
         states::plugin,
-        maze::Plugin { maze_type: MazeType::Wfc },
+        stats::plugin,
+        ascii::plugin,
+        print::plugin,
+        save::plugin,
+        game::plugin,
+        history::plugin,
+        cursors::plugin,
+        editor::plugin,
+        maze::maze::Plugin { maze_type: MazeType::Wfc, braid: 0.0, ..default() },
         ))
+    .add_plugins(cover::plugin)
     .add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()))
     .add_systems(Startup, setup)
-    .add_systems(OnEnter(GamePlayState::Playing), setup_player_and_goal)
     .add_systems(Update, (
         tileset_builder::construct_tileset,
         generate_bg,
-    ).run_if(in_state(GamePlayState::GeneratingMaze)))
-    .add_systems(
-        Update,
-        (move_player, check_goal).run_if(in_state(GamePlayState::Playing)),
-    )
+    ).run_if(in_state(states::AppState::InGame)))
     .add_systems(
         Update,
         toggle_debug_overlay
@@ -82,29 +125,14 @@ fn main() {
     app.run();
 }
 
-fn collide(transform1: &Transform, transform2: &Transform) -> bool {
-    (transform1.translation.xy() - transform2.translation.xy())
-        .abs()
-        .cmplt((transform1.scale.xy() + transform2.scale.xy()) / 2.0)
-        .all()
-}
+#[derive(Resource)]
+struct LoadedGrid(Grid);
 
-fn check_goal(
-    mut ev_appexit: EventWriter<AppExit>,
-    player_query: Query<&Transform, (With<Player>, Without<Goal>)>,
-    goal_query: Query<&Transform, (Without<Player>, With<Goal>)>,
-    mut next_state: ResMut<NextState<GamePlayState>>,
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loaded_grid: Option<Res<LoadedGrid>>,
 ) {
-    let player_transform = player_query.single();
-    let goal_transform = goal_query.single();
-
-    if collide(player_transform, goal_transform) {
-        next_state.set(GamePlayState::LevelDone);
-        ev_appexit.send(AppExit::Success);
-    }
-}
-
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn(Camera2dBundle {
         transform: Transform {
             translation: Vec3::new(GRID_WIDTH as f32 / 2., GRID_HEIGHT as f32 / 2., 0.0),
@@ -118,28 +146,38 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         tileset_builder::Tileset {
             tileset: asset_server.load("tileset4.png"),
         },
-        Grid::new(),
+        loaded_grid.map_or_else(Grid::new, |loaded| loaded.0.clone()),
         Transform::default().with_translation(Vec3::new(0.0, 0.0, 5.0)),
         Trees,
+        tilemap::TilePointer::default(),
         Name::from("Tilemap: Trees"),
     ));
 
+    // The background fills the screen with tiles of 32 pixels
+    const BG_TILE_PIXELS: f32 = 32.0;
+    let bg_tiles = Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT) / BG_TILE_PIXELS;
     commands.spawn((
         tilemap::Tileset {
             image: asset_server.load("bg.png"),
             num_tiles: 7,
         },
-        Tilemap::new((SCREEN_WIDTH / 32.0) as u32, (SCREEN_HEIGHT / 32.0) as u32),
+        Tilemap::new(bg_tiles.x as u32, bg_tiles.y as u32),
+        tilemap::TilemapLayout::centered(
+            bg_tiles,
+            Vec2::new(PIXEL_WIDTH, PIXEL_HEIGHT) * BG_TILE_PIXELS,
+            Vec2::new(GRID_WIDTH as f32, GRID_HEIGHT as f32) / 2.0,
+        ),
         Transform::default().with_translation(Vec3::new(0.0, 0.0, -5.0)),
         Ground,
         Name::from("Tilemap: Background"),
     ));
 
+    let cover_tileset = create_alpha_tileset(&asset_server);
+    let num_cover_tiles = cover_tileset.num_tiles;
     commands.spawn((
-        create_alpha_tileset(&asset_server),
-        Tilemap::new(GRID_WIDTH as u32, GRID_HEIGHT as u32),
+        cover_tileset,
+        cover::Cover::new(GRID_WIDTH as u32, GRID_HEIGHT as u32, num_cover_tiles),
         Transform::default().with_translation(Vec3::new(0.0, 0.0, 10.0)),
-        Cover::default(),
         Name::from("Tilemap: Cover"),
     ));
 
@@ -201,121 +239,14 @@ fn generate_bg(mut commands: Commands, mut query: Query<(Entity, &mut Tilemap),
 
     for x in 0..width {
         for y in 0..height {
-            tilemap.data[y * width + x] = rng.gen_range(-17i32..7).clamp(0, 7) as u8;
+            let tile = rng.gen_range(-17i32..7).clamp(0, 7) as u8;
+            tilemap.set(UVec2::new(x as u32, y as u32), tile);
         }
     }
 
     commands.entity(entity).remove::<Ground>();
 }
 
-fn setup_player_and_goal(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        SpriteBundle {
-            texture: asset_server.load("elephant-square.png"),
-
-            transform: Transform {
-                translation: Vec3::new(
-                    (GRID_WIDTH - 1) as f32 + 0.5,
-                    (GRID_HEIGHT - 1) as f32 + 0.5,
-                    1.,
-                ),
-                scale: Vec3::new(1.0 / 192.0, 1.0 / 192.0, 1.0),
-                ..default()
-            },
-            ..default()
-        },
-        Goal,
-        Name::from("Goal"),
-    ));
-
-    commands.spawn((
-        SpriteBundle {
-            texture: asset_server.load("elephant-round.png"),
-            transform: Transform {
-                translation: Vec3::new(0.5, 0.5, 2.),
-                scale: Vec3::new(PLAYER_WIDTH / 192.0, PLAYER_HEIGHT / 192.0, 1.0),
-                ..default()
-            },
-            ..default()
-        },
-        Name::from("Player"),
-        Player,
-    ));
-}
-
-fn move_player(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut player_query: Query<&mut Transform, With<Player>>,
-    tilemap_query: Query<&Grid, With<Trees>>,
-    time: Res<Time>,
-) {
-    let mut player_transform = player_query.single_mut();
-    let grid = tilemap_query.single();
-
-    let mut direction = Vec3::new(0., 0., 0.);
-
-    if keyboard_input.pressed(KeyCode::KeyW) || keyboard_input.pressed(KeyCode::ArrowUp) {
-        direction.y += 1.;
-    }
-    if keyboard_input.pressed(KeyCode::KeyS) || keyboard_input.pressed(KeyCode::ArrowDown) {
-        direction.y -= 1.;
-    }
-    if keyboard_input.pressed(KeyCode::KeyA) || keyboard_input.pressed(KeyCode::ArrowLeft) {
-        direction.x -= 1.;
-    }
-    if keyboard_input.pressed(KeyCode::KeyD) || keyboard_input.pressed(KeyCode::ArrowRight) {
-        direction.x += 1.;
-    }
-
-    direction *= PLAYER_SPEED * time.delta_seconds();
-
-    if keyboard_input.pressed(KeyCode::ControlLeft) {
-        direction *= 4.0;
-    }
-
-    let pos = player_transform.translation.xy().floor();
-    let ipos = pos.as_ivec2();
-
-    let is_between = (player_transform.translation.xy() - (pos + Vec2::new(0.5, 0.5)))
-        .abs()
-        .cmpgt(Vec2::new(
-            (1.0 - PLAYER_WIDTH) / 2.0,
-            (1.0 - PLAYER_HEIGHT) / 2.0,
-        ));
-
-    let min_x = if is_between.y || grid.has_wall(ipos, Dir::West) {
-        pos.x
-    } else {
-        0.0
-    };
-    let max_x = if is_between.y || grid.has_wall(ipos, Dir::East) {
-        pos.x + 1.0
-    } else {
-        GRID_WIDTH as f32
-    };
-    let min_y = if is_between.x || grid.has_wall(ipos, Dir::South) {
-        pos.y
-    } else {
-        0.0
-    };
-    let max_y = if is_between.x || grid.has_wall(ipos, Dir::North) {
-        pos.y + 1.0
-    } else {
-        GRID_HEIGHT as f32
-    };
-
-    let d = Vec3::new(
-        PLAYER_WIDTH / 2.0 + PIXEL_WIDTH,
-        PLAYER_HEIGHT / 2.0 + PIXEL_HEIGHT,
-        0.0,
-    );
-
-    player_transform.translation = (player_transform.translation + direction).clamp(
-        Vec3::new(min_x, min_y, 0.) + d,
-        Vec3::new(max_x, max_y, 0.) - d,
-    );
-}
-
 pub fn close_on_esc(
     mut commands: Commands,
     focused_windows: Query<(Entity, &Window)>,
@@ -1,19 +1,27 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, time::common_conditions::on_timer};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     components::*,
     consts::*,
-    grid::Grid,
+    grid::{Dir, Grid},
+    history::History,
     states::{AppState, GamePlayState},
 };
 
+mod background;
 mod backtracking;
 mod kruskal;
 mod wfc;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub use background::GenerationTask;
+pub use backtracking::{CursorConfig, CursorError, CursorStart};
+pub use kruskal::{EdgeWeights, WeightFn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum MazeType {
     #[default]
@@ -22,6 +30,21 @@ pub enum MazeType {
     Wfc,
 }
 
+impl MazeType {
+    pub const ALL: [MazeType; 3] = [MazeType::Backtracking, MazeType::Kruskal, MazeType::Wfc];
+}
+
+impl std::str::FromStr for MazeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MazeType::ALL
+            .into_iter()
+            .find(|maze_type| format!("{:?}", maze_type).eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown maze type '{}'", s))
+    }
+}
+
 #[derive(Component)]
 pub enum MazeState {
     Backtracking(backtracking::MazeState),
@@ -29,95 +52,385 @@ pub enum MazeState {
     Wfc(wfc::MazeState),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Component)]
+pub struct MazeRng(pub StdRng);
+
+/// What a generator is doing with a cell, see `MazeState::active_cells`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveCell {
+    /// The cell a cursor is at
+    Head(usize),
+    /// A cell on the stack of a cursor, which it can backtrack to
+    Stack(usize),
+    /// A cell a cursor has visited first
+    Region(usize),
+    /// A cell next to the wall which is considered next
+    Frontier,
+    /// A cell the generator could have picked in the last step
+    Candidate,
+}
+
+/// How quickly the maze is generated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GenerationMode {
+    /// Run `steps` generator steps every `interval`
+    StepsPerTick { steps: usize, interval: Duration },
+    /// Spread the steps evenly over the frames
+    StepsPerSecond(f32),
+    /// Run the generator to completion in a single frame
+    Instant,
+    /// Run the generator on a background task, showing a snapshot of the grid
+    /// every `snapshot_steps` steps
+    Background { snapshot_steps: usize },
+}
+
+impl Default for GenerationMode {
+    fn default() -> Self {
+        GenerationMode::StepsPerTick {
+            steps: 1,
+            interval: Duration::from_millis(MAZE_GEN_TIME_MS),
+        }
+    }
+}
+
+/// Decides how many generator steps to run in a frame
+#[derive(Resource)]
+struct GenerationClock {
+    mode: GenerationMode,
+    timer: Timer,
+    carry: f32,
+}
+
+impl GenerationClock {
+    fn new(mode: GenerationMode) -> Self {
+        let interval = match mode {
+            GenerationMode::StepsPerTick { interval, .. } => interval,
+            _ => Duration::ZERO,
+        };
+        Self {
+            mode,
+            timer: Timer::new(interval, TimerMode::Repeating),
+            carry: 0.0,
+        }
+    }
+
+    fn steps(&mut self, delta: Duration) -> usize {
+        match self.mode {
+            GenerationMode::StepsPerTick { steps, .. } => {
+                self.timer.tick(delta);
+                steps * self.timer.times_finished_this_tick() as usize
+            }
+            GenerationMode::StepsPerSecond(rate) => {
+                self.carry += rate * delta.as_secs_f32();
+                let steps = self.carry.floor();
+                self.carry -= steps;
+                steps as usize
+            }
+            GenerationMode::Instant => usize::MAX,
+            GenerationMode::Background { .. } => 0,
+        }
+    }
+}
+
+/// The fraction of the maze which has been generated, from 0 to 1
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default)]
+pub struct GenerationProgress(pub f32);
+
+impl GenerationProgress {
+    pub fn new(grid: &Grid) -> Self {
+        let joins = grid.num_cells().saturating_sub(1);
+        if joins == 0 {
+            return Self(1.0);
+        }
+        Self((grid.num_cells() - grid.regions.num_sets()) as f32 / joins as f32)
+    }
+}
+
+#[derive(Component)]
+struct ProgressText;
+
+/// Settings of the generators which only apply to some maze types
+#[derive(Resource, Debug, Clone, PartialEq, Default)]
+pub struct GeneratorConfig {
+    /// Where the backtracking cursors start
+    pub cursors: CursorConfig,
+    /// The order in which Kruskal removes walls
+    pub weights: EdgeWeights,
+}
+
+/// How the current maze was generated
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub maze_type: MazeType,
+    pub seed: u64,
+    pub braid: f32,
+}
+
+impl MazeState {
+    pub fn new(maze_type: MazeType, width: usize, height: usize, rng: &mut impl Rng) -> Self {
+        Self::with_config(maze_type, width, height, &GeneratorConfig::default(), rng)
+            .expect("The default config should be valid")
+    }
+
+    /// A generator using the settings in `config` which apply to it. Fails if
+    /// the backtracking cursors can not be started.
+    pub fn with_config(
+        maze_type: MazeType,
+        width: usize,
+        height: usize,
+        config: &GeneratorConfig,
+        rng: &mut impl Rng,
+    ) -> Result<Self, CursorError> {
+        Ok(match maze_type {
+            MazeType::Backtracking => {
+                MazeState::Backtracking(backtracking::init(width, height, &config.cursors, rng)?)
+            }
+            MazeType::Kruskal => {
+                MazeState::Kruskal(kruskal::init(width, height, &config.weights, rng))
+            }
+            MazeType::Wfc => MazeState::Wfc(wfc::init(width, height)),
+        })
+    }
+
+    /// The cells the generator is working on, for generators which move
+    /// cursors through the maze. The index of a cursor stays the same between
+    /// steps.
+    pub fn cursors(&self) -> Vec<Option<IVec2>> {
+        match self {
+            MazeState::Backtracking(maze_state) => backtracking::cursors(maze_state),
+            MazeState::Kruskal(_) | MazeState::Wfc(_) => Vec::new(),
+        }
+    }
+
+    /// The cells the generator is working on. A cell can be listed more than
+    /// once, the last entry is the most important one.
+    pub fn active_cells(&self) -> Vec<(IVec2, ActiveCell)> {
+        match self {
+            MazeState::Backtracking(maze_state) => backtracking::active_cells(maze_state),
+            MazeState::Kruskal(maze_state) => kruskal::active_cells(maze_state),
+            MazeState::Wfc(maze_state) => wfc::active_cells(maze_state),
+        }
+    }
+
+    pub fn step(&mut self, grid: &mut Grid, rng: &mut impl Rng) {
+        match self {
+            MazeState::Backtracking(maze_state) => backtracking::step(maze_state, grid, rng),
+            MazeState::Kruskal(maze_state) => kruskal::step(maze_state, grid),
+            MazeState::Wfc(maze_state) => wfc::step(maze_state, grid, rng),
+        }
+    }
+}
+
+/// Run the generator to completion without any animation.
+pub fn generate_grid(maze_type: MazeType, width: usize, height: usize, rng: &mut impl Rng) -> Grid {
+    generate_grid_with(maze_type, width, height, &GeneratorConfig::default(), rng)
+        .expect("The default config should be valid")
+}
+
+/// Like `generate_grid`, using the settings in `config`
+pub fn generate_grid_with(
+    maze_type: MazeType,
+    width: usize,
+    height: usize,
+    config: &GeneratorConfig,
+    rng: &mut impl Rng,
+) -> Result<Grid, CursorError> {
+    let mut grid = Grid::with_size(width, height);
+    let mut state = MazeState::with_config(maze_type, width, height, config, rng)?;
+    while grid.regions.num_sets() > 1 {
+        state.step(&mut grid, rng);
+    }
+    Ok(grid)
+}
+
+/// Remove a fraction `factor` of the dead ends by opening one of their walls,
+/// preferably towards another dead end. This adds loops to the maze.
+pub fn braid(grid: &mut Grid, factor: f32, rng: &mut impl Rng) {
+    if factor <= 0.0 {
+        return;
+    }
+
+    let is_dead_end = |grid: &Grid, pos: IVec2| grid.open_dirs(pos).count() == 1;
+
+    let mut dead_ends: Vec<IVec2> = grid
+        .positions()
+        .filter(|pos| is_dead_end(grid, *pos))
+        .collect();
+    dead_ends.shuffle(rng);
+
+    for pos in dead_ends {
+        if !rng.gen_bool(factor.min(1.0) as f64) || !is_dead_end(grid, pos) {
+            continue;
+        }
+
+        let closed: Vec<Dir> = Dir::ALL
+            .into_iter()
+            .filter(|dir| grid.has_wall(pos, *dir) && grid.is_inside(pos + IVec2::from(*dir)))
+            .collect();
+        let preferred: Vec<Dir> = closed
+            .iter()
+            .copied()
+            .filter(|dir| is_dead_end(grid, pos + IVec2::from(*dir)))
+            .collect();
+
+        let choices = if preferred.is_empty() {
+            closed
+        } else {
+            preferred
+        };
+        if let Some(dir) = choices.choose(rng) {
+            grid.open_wall(pos, *dir);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Plugin {
     pub maze_type: MazeType,
+    pub braid: f32,
+    pub mode: GenerationMode,
+    /// The initial `GeneratorConfig`, which can be changed between levels
+    pub config: GeneratorConfig,
 }
 
 impl bevy::prelude::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         let maze_type = self.maze_type;
-        app.add_systems(
-            OnEnter(GamePlayState::GeneratingMaze),
-            move |commands: Commands| setup(commands, maze_type),
-        )
-        .add_systems(
-            Update,
-            generate
-                .run_if(on_timer(Duration::from_millis(MAZE_GEN_TIME_MS)))
-                .run_if(in_state(GamePlayState::GeneratingMaze)),
-        )
-        .add_systems(
-            Update,
-            (update_cover, update_overlay).run_if(in_state(AppState::InGame)),
-        );
+        let braid = self.braid;
+        let mode = self.mode;
+        app.init_resource::<GenerationProgress>()
+            .insert_resource(self.config.clone())
+            .add_systems(
+                OnEnter(GamePlayState::GeneratingMaze),
+                (
+                    move |commands: Commands, config: Res<GeneratorConfig>| {
+                        setup(commands, maze_type, braid, &config)
+                    },
+                    move |mut commands: Commands| {
+                        commands.insert_resource(GenerationClock::new(mode))
+                    },
+                    setup_progress,
+                ),
+            )
+            .add_systems(
+                OnExit(GamePlayState::GeneratingMaze),
+                (cleanup_progress, background::cancel_tasks),
+            )
+            .add_systems(
+                Update,
+                (
+                    generate,
+                    background::start_task,
+                    background::poll_task,
+                    update_progress,
+                )
+                    .chain()
+                    .run_if(in_state(GamePlayState::GeneratingMaze)),
+            )
+            .add_systems(Update, update_overlay.run_if(in_state(AppState::InGame)));
     }
 }
 
-pub fn setup(mut commands: Commands, maze_type: MazeType) {
-    let state = match maze_type {
-        MazeType::Backtracking => MazeState::Backtracking(backtracking::init()),
-        MazeType::Kruskal => MazeState::Kruskal(kruskal::init()),
-        MazeType::Wfc => MazeState::Wfc(wfc::init()),
-    };
-    commands.spawn(state);
+pub fn setup(mut commands: Commands, maze_type: MazeType, braid: f32, config: &GeneratorConfig) {
+    let seed = rand::random();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let state = MazeState::with_config(maze_type, GRID_WIDTH, GRID_HEIGHT, config, &mut rng)
+        .unwrap_or_else(|err| {
+            error!("Invalid generator config, using the default one: {}", err);
+            MazeState::new(maze_type, GRID_WIDTH, GRID_HEIGHT, &mut rng)
+        });
+    commands.spawn((state, MazeRng(rng)));
+    commands.insert_resource(History::new(GRID_WIDTH, GRID_HEIGHT));
+    commands.insert_resource(Level {
+        maze_type,
+        seed,
+        braid,
+    });
 }
 
-pub fn generate(
-    mut state_query: Query<&mut MazeState>,
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    mut state_query: Query<(&mut MazeState, &mut MazeRng)>,
     mut grid_query: Query<&mut Grid, With<Trees>>,
-    mut next_state: ResMut<NextState<crate::GamePlayState>>,
+    level: Res<Level>,
+    mut clock: ResMut<GenerationClock>,
+    mut progress: ResMut<GenerationProgress>,
+    mut history: ResMut<History>,
+    time: Res<Time>,
+    mut next_state: ResMut<NextState<GamePlayState>>,
 ) {
     let Ok(mut grid) = grid_query.get_single_mut() else {
         return;
     };
 
-    let Ok(mut state) = state_query.get_single_mut() else {
+    let Ok((mut state, mut rng)) = state_query.get_single_mut() else {
         return;
     };
 
-    let state = &mut *state;
+    let steps = clock.steps(time.delta());
+    if steps == 0 {
+        return;
+    }
+
     let grid = &mut *grid;
+    grid.start_recording();
 
-    for _ in 0..1 {
+    for _ in 0..steps {
         if grid.regions.num_sets() == 1 {
             println!("Maze done");
-            next_state.set(crate::GamePlayState::Playing);
-            return;
+            braid(grid, level.braid, &mut rng.0);
+            history.record(grid, &[]);
+            next_state.set(GamePlayState::Playing);
+            break;
         }
 
-        match state {
-            MazeState::Backtracking(maze_state) => backtracking::step(maze_state, grid),
-            MazeState::Kruskal(maze_state) => kruskal::step(maze_state, grid),
-            MazeState::Wfc(maze_state) => wfc::step(maze_state, grid),
-        }
+        state.step(grid, &mut rng.0);
+        history.record(grid, &state.cursors());
     }
+
+    *progress = GenerationProgress::new(grid);
 }
 
-pub fn update_cover(
-    grid_query: Query<&Grid>,
-    mut cover_query: Query<(&mut crate::tilemap::Tilemap, &mut Cover)>,
-    time: Res<Time>,
+fn setup_progress(mut commands: Commands, mut progress: ResMut<GenerationProgress>) {
+    *progress = GenerationProgress::default();
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(MARGIN),
+            left: Val::Px(MARGIN),
+            ..default()
+        }),
+        ProgressText,
+        Name::from("Progress"),
+    ));
+}
+
+fn update_progress(
+    progress: Res<GenerationProgress>,
+    mut text_query: Query<&mut Text, With<ProgressText>>,
 ) {
-    let Ok((mut tilemap, mut cover)) = cover_query.get_single_mut() else {
+    if !progress.is_changed() {
         return;
-    };
+    }
 
-    let Ok(grid) = grid_query.get_single() else {
+    let Ok(mut text) = text_query.get_single_mut() else {
         return;
     };
 
-    let inc = cover.step(time.delta_seconds());
+    text.sections[0].value = format!("Generating {:.0}%", progress.0 * 100.0);
+}
 
-    for y in 0..GRID_HEIGHT {
-        for x in 0..GRID_WIDTH {
-            if !grid.is_visited(IVec2::new(x as i32, y as i32)) {
-                tilemap.data[y * GRID_WIDTH + x] = 0;
-            } else {
-                tilemap.data[y * GRID_WIDTH + x] =
-                    tilemap.data[y * GRID_WIDTH + x].saturating_add(inc as u8);
-            }
-        }
+fn cleanup_progress(mut commands: Commands, text_query: Query<Entity, With<ProgressText>>) {
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn();
     }
 }
 
@@ -133,8 +446,10 @@ pub fn update_overlay(
         return;
     };
 
-    for (i, n) in grid.regions.values().enumerate() {
-        overlay.data[i] = (n & 0xFF) as u8;
+    for root in grid.regions.sets() {
+        for i in grid.regions.members(root) {
+            overlay.data[i] = (root & 0xFF) as u8;
+        }
     }
 
     println!(
@@ -146,3 +461,160 @@ pub fn update_overlay(
             .unwrap()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::solver;
+
+    /// A bound on the steps needed for `cells` cells. Wfc opens each wall with
+    /// a probability of one half, so tiny mazes need some slack.
+    fn max_steps(cells: usize) -> usize {
+        4 * cells + 64
+    }
+
+    /// Like `generate_grid`, but fails if the generator takes more than
+    /// `max_steps` steps
+    fn generate_bounded(
+        maze_type: MazeType,
+        width: usize,
+        height: usize,
+        seed: u64,
+        max_steps: usize,
+    ) -> Result<Grid, TestCaseError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid = Grid::with_size(width, height);
+        let mut state = MazeState::new(maze_type, width, height, &mut rng);
+        let mut steps = 0;
+        while grid.regions.num_sets() > 1 {
+            prop_assert!(
+                steps < max_steps,
+                "{:?} did not finish a {}x{} maze within {} steps",
+                maze_type,
+                width,
+                height,
+                max_steps
+            );
+            state.step(&mut grid, &mut rng);
+            steps += 1;
+        }
+        Ok(grid)
+    }
+
+    fn num_openings(grid: &Grid) -> usize {
+        let bits: usize = grid.walls().iter().map(|w| w.count_ones() as usize).sum();
+        bits / 2
+    }
+
+    /// Every opening is seen from both sides and none lead out of the grid
+    fn check_walls(grid: &Grid) -> Result<(), TestCaseError> {
+        for pos in grid.positions() {
+            for dir in Dir::ALL {
+                let open = grid.get_walls(pos) & dir as u8 != 0;
+                let next = pos + IVec2::from(dir);
+                if !grid.is_inside(next) {
+                    prop_assert!(!open, "{:?} of {} opens the border", dir, pos);
+                    continue;
+                }
+                let reverse = grid.get_walls(next) & dir.reverse() as u8 != 0;
+                prop_assert_eq!(open, reverse, "{:?} of {} is not symmetric", dir, pos);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_reachable(grid: &Grid) -> Result<(), TestCaseError> {
+        let distances = solver::distances(grid, IVec2::ZERO);
+        prop_assert!(distances.iter().all(Option::is_some));
+        Ok(())
+    }
+
+    fn maze_type() -> impl Strategy<Value = MazeType> {
+        prop::sample::select(MazeType::ALL.to_vec())
+    }
+
+    proptest! {
+        #[test]
+        fn test_perfect_maze(
+            maze_type in maze_type(),
+            width in 1..16usize,
+            height in 1..16usize,
+            seed: u64,
+        ) {
+            let cells = width * height;
+            let grid = generate_bounded(maze_type, width, height, seed, max_steps(cells))?;
+
+            check_walls(&grid)?;
+            check_reachable(&grid)?;
+            prop_assert_eq!(num_openings(&grid), cells - 1);
+        }
+
+        #[test]
+        fn test_braided_maze(
+            maze_type in maze_type(),
+            width in 2..16usize,
+            height in 2..16usize,
+            seed: u64,
+            factor in 0.0..=1.0f32,
+        ) {
+            let mut grid = generate_bounded(maze_type, width, height, seed, max_steps(width * height))?;
+            let mut rng = StdRng::seed_from_u64(seed);
+            braid(&mut grid, factor, &mut rng);
+
+            check_walls(&grid)?;
+            check_reachable(&grid)?;
+            prop_assert!(num_openings(&grid) >= width * height - 1);
+            if factor >= 1.0 {
+                prop_assert!(grid.positions().all(|pos| grid.open_dirs(pos).count() > 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_generation_clock() {
+        let frame = Duration::from_millis(10);
+
+        let mut clock = GenerationClock::new(GenerationMode::default());
+        let steps: Vec<usize> = (0..5).map(|_| clock.steps(frame)).collect();
+        assert_eq!(steps, [0, 0, 1, 0, 1]);
+
+        let mut clock = GenerationClock::new(GenerationMode::StepsPerTick {
+            steps: 3,
+            interval: Duration::from_millis(4),
+        });
+        assert_eq!(clock.steps(frame), 6);
+        assert_eq!(clock.steps(frame), 9);
+
+        let mut clock = GenerationClock::new(GenerationMode::StepsPerSecond(250.0));
+        let steps: usize = (0..100).map(|_| clock.steps(frame)).sum();
+        assert!((249..=250).contains(&steps));
+
+        let mut clock = GenerationClock::new(GenerationMode::Instant);
+        assert_eq!(clock.steps(Duration::ZERO), usize::MAX);
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut grid = Grid::with_size(3, 1);
+        assert_eq!(GenerationProgress::new(&grid).0, 0.0);
+        grid.open_wall(IVec2::ZERO, Dir::East);
+        assert_eq!(GenerationProgress::new(&grid).0, 0.5);
+        assert_eq!(GenerationProgress::new(&Grid::with_size(1, 1)).0, 1.0);
+    }
+
+    #[test]
+    fn test_kruskal_steps() {
+        // Every Kruskal step joins exactly two regions
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut grid = Grid::with_size(9, 7);
+            let mut state = MazeState::new(MazeType::Kruskal, 9, 7, &mut rng);
+            for steps in 1..grid.num_cells() {
+                state.step(&mut grid, &mut rng);
+                assert_eq!(grid.regions.num_sets(), grid.num_cells() - steps);
+            }
+        }
+    }
+}
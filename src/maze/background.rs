@@ -0,0 +1,204 @@
+//! Running the generator on the `AsyncComputeTaskPool`, so that large mazes do
+//! not stall the main schedule
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, TrySendError},
+    Arc, Mutex,
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use rand::rngs::StdRng;
+
+use super::{braid, GenerationClock, GenerationMode, GenerationProgress, MazeRng, MazeState};
+use crate::{components::*, grid::Grid, history::History, maze::Level, states::GamePlayState};
+
+/// A generator running on a background task. The task stops when this is
+/// dropped, for example when its entity is despawned.
+#[derive(Component)]
+pub struct GenerationTask {
+    task: Task<Option<(Grid, History)>>,
+    snapshots: Mutex<Receiver<Grid>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl GenerationTask {
+    /// Continue generating `grid` on a background task, sending a snapshot
+    /// every `snapshot_steps` steps and braiding the maze once it is done. The
+    /// task returns the final grid together with its history.
+    pub fn spawn(
+        mut state: MazeState,
+        mut rng: StdRng,
+        mut grid: Grid,
+        braid_factor: f32,
+        snapshot_steps: usize,
+    ) -> Self {
+        // Only one snapshot is buffered, the others are skipped if the main
+        // world is slower than the generator
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = cancel.clone();
+
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            let mut history = History::new(grid.width(), grid.height());
+            grid.start_recording();
+
+            let mut steps = 0;
+            while grid.regions.num_sets() > 1 {
+                if cancelled.load(Ordering::Relaxed) {
+                    return None;
+                }
+
+                state.step(&mut grid, &mut rng);
+                history.record(&mut grid, &state.cursors());
+                steps += 1;
+
+                if steps % snapshot_steps.max(1) == 0 {
+                    if let Err(TrySendError::Disconnected(_)) = sender.try_send(grid.clone()) {
+                        return None;
+                    }
+                }
+            }
+            braid(&mut grid, braid_factor, &mut rng);
+            history.record(&mut grid, &[]);
+            Some((grid, history))
+        });
+
+        Self {
+            task,
+            snapshots: Mutex::new(receiver),
+            cancel,
+        }
+    }
+
+    /// Ask the task to stop at its next step
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// The most recent snapshot which has not been seen yet
+    fn latest_snapshot(&mut self) -> Option<Grid> {
+        self.snapshots.get_mut().ok()?.try_iter().last()
+    }
+}
+
+impl Drop for GenerationTask {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Hand the generator set up by `setup` over to a background task, starting
+/// from the current grid
+pub(super) fn start_task(
+    mut commands: Commands,
+    state_query: Query<Entity, (With<MazeState>, Without<GenerationTask>)>,
+    grid_query: Query<&Grid, With<Trees>>,
+    level: Res<Level>,
+    clock: Res<GenerationClock>,
+) {
+    let GenerationMode::Background { snapshot_steps } = clock.mode else {
+        return;
+    };
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    for entity in state_query.iter() {
+        let grid = grid.clone();
+        let braid_factor = level.braid;
+        commands.add(move |world: &mut World| {
+            let mut entity = world.entity_mut(entity);
+            let Some((state, MazeRng(rng))) = entity.take::<(MazeState, MazeRng)>() else {
+                return;
+            };
+            entity.insert(GenerationTask::spawn(
+                state,
+                rng,
+                grid,
+                braid_factor,
+                snapshot_steps,
+            ));
+        });
+    }
+}
+
+/// Show the latest snapshot and finish the level once the task is done
+pub(super) fn poll_task(
+    mut commands: Commands,
+    mut task_query: Query<(Entity, &mut GenerationTask)>,
+    mut grid_query: Query<&mut Grid, With<Trees>>,
+    mut progress: ResMut<GenerationProgress>,
+    mut history: ResMut<History>,
+    mut next_state: ResMut<NextState<GamePlayState>>,
+) {
+    let Ok(mut grid) = grid_query.get_single_mut() else {
+        return;
+    };
+
+    for (entity, mut task) in task_query.iter_mut() {
+        if let Some(snapshot) = task.latest_snapshot() {
+            *grid = snapshot;
+        }
+
+        if let Some(result) = block_on(poll_once(&mut task.task)) {
+            commands.entity(entity).despawn();
+            if let Some((done, done_history)) = result {
+                info!("Maze done");
+                *grid = done;
+                *history = done_history;
+                next_state.set(GamePlayState::Playing);
+            }
+        }
+
+        *progress = GenerationProgress::new(&grid);
+    }
+}
+
+/// Stop any generator still running, e.g. when restarting mid-generation
+pub(super) fn cancel_tasks(
+    mut commands: Commands,
+    task_query: Query<Entity, With<GenerationTask>>,
+) {
+    for entity in task_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::maze::MazeType;
+
+    #[test]
+    fn test_task() {
+        AsyncComputeTaskPool::get_or_init(Default::default);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let state = MazeState::new(MazeType::Kruskal, 16, 16, &mut rng);
+        let mut task = GenerationTask::spawn(state, rng, Grid::with_size(16, 16), 0.0, 8);
+
+        let (grid, history) = block_on(&mut task.task).unwrap();
+        assert_eq!(grid.regions.num_sets(), 1);
+        assert_eq!(history.grid_at(history.len()).walls(), grid.walls());
+        let snapshot = task.latest_snapshot().unwrap();
+        assert!(snapshot.regions.num_sets() < 256);
+    }
+
+    #[test]
+    fn test_cancel() {
+        AsyncComputeTaskPool::get_or_init(Default::default);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let state = MazeState::new(MazeType::Backtracking, 1024, 1024, &mut rng);
+        let mut task = GenerationTask::spawn(state, rng, Grid::with_size(1024, 1024), 0.0, 1);
+
+        task.cancel();
+        assert!(block_on(&mut task.task).is_none());
+    }
+}
@@ -1,5 +1,196 @@
-use crate::{consts::*, grid::Grid};
+use std::{fmt, str::FromStr};
+
+use super::ActiveCell;
+use crate::grid::Grid;
 use bevy::math::IVec2;
+use rand::{seq::index, Rng};
+
+/// Where the cursors of the backtracking generator start
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CursorStart {
+    /// Distinct random cells
+    Random,
+    /// The corners of the grid, any further cursors start at random cells
+    #[default]
+    Corners,
+    /// The centres of equally sized blocks covering the grid
+    Even,
+    /// The given cells, one cursor each
+    Positions(Vec<IVec2>),
+}
+
+impl FromStr for CursorStart {
+    type Err = String;
+
+    /// Parses `random`, `corners`, `even` or a list of cells such as
+    /// `0,0;3,4`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(CursorStart::Random),
+            "corners" => Ok(CursorStart::Corners),
+            "even" => Ok(CursorStart::Even),
+            _ => s
+                .split(';')
+                .map(|cell| {
+                    let (x, y) = cell
+                        .split_once(',')
+                        .ok_or_else(|| format!("expected x,y but got '{}'", cell))?;
+                    let parse = |n: &str| {
+                        n.trim()
+                            .parse::<i32>()
+                            .map_err(|_| format!("invalid coordinate '{}'", n))
+                    };
+                    Ok(IVec2::new(parse(x)?, parse(y)?))
+                })
+                .collect::<Result<_, String>>()
+                .map(CursorStart::Positions),
+        }
+    }
+}
+
+/// The number of cursors of the backtracking generator and where they start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorConfig {
+    /// Ignored for `CursorStart::Positions`, which has one cursor per cell
+    pub count: usize,
+    pub start: CursorStart,
+}
+
+/// Why the cursors of a `CursorConfig` can not be started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorError {
+    /// The count is zero or the list of cells is empty, so the generator
+    /// would never finish
+    NoCursors,
+    Outside {
+        pos: IVec2,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::NoCursors => write!(f, "at least one cursor is needed"),
+            CursorError::Outside { pos, width, height } => write!(
+                f,
+                "the cursor at {pos} is outside the {width}x{height} maze"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            start: CursorStart::Corners,
+        }
+    }
+}
+
+impl CursorConfig {
+    /// Check that at least one cursor starts and that all the given cells are
+    /// inside a grid of `width` by `height` cells
+    pub fn validate(&self, width: usize, height: usize) -> Result<(), CursorError> {
+        match &self.start {
+            CursorStart::Positions(positions) => {
+                if positions.is_empty() {
+                    return Err(CursorError::NoCursors);
+                }
+                let size = IVec2::new(width as i32, height as i32);
+                match positions
+                    .iter()
+                    .find(|pos| pos.cmplt(IVec2::ZERO).any() || pos.cmpge(size).any())
+                {
+                    Some(pos) => Err(CursorError::Outside {
+                        pos: *pos,
+                        width,
+                        height,
+                    }),
+                    None => Ok(()),
+                }
+            }
+            _ if self.count == 0 => Err(CursorError::NoCursors),
+            _ => Ok(()),
+        }
+    }
+
+    /// The start cell of every cursor. There is at most one cursor per cell.
+    pub fn start_positions(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<IVec2>, CursorError> {
+        self.validate(width, height)?;
+        let num_cells = width * height;
+        let count = self.count.min(num_cells);
+        let cell = |i: usize| IVec2::new((i % width) as i32, (i / width) as i32);
+
+        Ok(match &self.start {
+            CursorStart::Random => index::sample(rng, num_cells, count)
+                .into_iter()
+                .map(cell)
+                .collect(),
+            CursorStart::Corners => {
+                let (w, h) = (width as i32, height as i32);
+                let mut positions: Vec<IVec2> = Vec::with_capacity(count);
+                let corners = [
+                    IVec2::new(0, 0),
+                    IVec2::new(w - 1, h - 1),
+                    IVec2::new(w - 1, 0),
+                    IVec2::new(0, h - 1),
+                ];
+                for corner in corners {
+                    if positions.len() < count && !positions.contains(&corner) {
+                        positions.push(corner);
+                    }
+                }
+                // Pick the rest among the remaining cells
+                let rest: Vec<usize> = (0..num_cells)
+                    .filter(|i| !positions.contains(&cell(*i)))
+                    .collect();
+                let extra = count - positions.len();
+                positions.extend(
+                    index::sample(rng, rest.len(), extra)
+                        .into_iter()
+                        .map(|i| cell(rest[i])),
+                );
+                positions
+            }
+            CursorStart::Even => {
+                // Split the grid into columns and rows of roughly square blocks
+                let columns = ((count * width) as f32 / height as f32)
+                    .sqrt()
+                    .ceil()
+                    .clamp(1.0, width as f32) as usize;
+                let rows = count.div_ceil(columns).min(height);
+                (0..count.min(columns * rows))
+                    .map(|n| {
+                        let (column, row) = (n % columns, n / columns);
+                        IVec2::new(
+                            ((2 * column + 1) * width / (2 * columns)) as i32,
+                            ((2 * row + 1) * height / (2 * rows)) as i32,
+                        )
+                    })
+                    .collect()
+            }
+            CursorStart::Positions(positions) => {
+                let mut unique: Vec<IVec2> = Vec::with_capacity(positions.len());
+                for pos in positions {
+                    if !unique.contains(pos) {
+                        unique.push(*pos);
+                    }
+                }
+                unique
+            }
+        })
+    }
+}
 
 struct MazeCursor {
     path: Vec<IVec2>,
@@ -7,25 +198,65 @@ struct MazeCursor {
 
 pub struct MazeState {
     cursors: Vec<MazeCursor>,
+    /// The cursor which visited each cell first
+    owners: Vec<Option<usize>>,
+    width: usize,
 }
 
-pub fn init() -> MazeState {
-    let corner = [
-        IVec2::new(0, 0),
-        IVec2::new(GRID_WIDTH as i32 - 1, GRID_HEIGHT as i32 - 1),
-        IVec2::new(GRID_WIDTH as i32 - 1, 0),
-        IVec2::new(0, GRID_HEIGHT as i32 - 1),
-    ];
-    let cursors = (0..NUM_CURSORS as usize)
-        .map(|n| MazeCursor {
-            path: vec![corner[n]],
+pub fn init(
+    width: usize,
+    height: usize,
+    config: &CursorConfig,
+    rng: &mut impl Rng,
+) -> Result<MazeState, CursorError> {
+    let mut owners = vec![None; width * height];
+    let cursors = config
+        .start_positions(width, height, rng)?
+        .into_iter()
+        .enumerate()
+        .map(|(n, pos)| {
+            owners[pos.y as usize * width + pos.x as usize] = Some(n);
+            MazeCursor { path: vec![pos] }
         })
         .collect();
-    MazeState { cursors }
+    Ok(MazeState {
+        cursors,
+        owners,
+        width,
+    })
 }
 
-pub fn step(state: &mut MazeState, grid: &mut Grid) {
-    for cursor in &mut state.cursors {
+/// The current cell of every cursor, or `None` once it has backtracked all the
+/// way
+pub fn cursors(state: &MazeState) -> Vec<Option<IVec2>> {
+    state
+        .cursors
+        .iter()
+        .map(|cursor| cursor.path.last().copied())
+        .collect()
+}
+
+pub fn active_cells(state: &MazeState) -> Vec<(IVec2, ActiveCell)> {
+    let mut cells: Vec<(IVec2, ActiveCell)> = state
+        .owners
+        .iter()
+        .enumerate()
+        .filter_map(|(i, owner)| {
+            let pos = IVec2::new((i % state.width) as i32, (i / state.width) as i32);
+            owner.map(|n| (pos, ActiveCell::Region(n)))
+        })
+        .collect();
+    for (n, cursor) in state.cursors.iter().enumerate() {
+        if let Some((head, stack)) = cursor.path.split_last() {
+            cells.extend(stack.iter().map(|pos| (*pos, ActiveCell::Stack(n))));
+            cells.push((*head, ActiveCell::Head(n)));
+        }
+    }
+    cells
+}
+
+pub fn step(state: &mut MazeState, grid: &mut Grid, rng: &mut impl Rng) {
+    for (n, cursor) in state.cursors.iter_mut().enumerate() {
         let Some(pos) = cursor.path.last().copied() else {
             continue;
         };
@@ -37,12 +268,128 @@ pub fn step(state: &mut MazeState, grid: &mut Grid) {
             continue;
         }
 
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
         let index = rng.gen_range(0..possibilities.len());
         let dir = possibilities[index];
 
         let _ = grid.remove_wall(pos, dir);
-        cursor.path.push(pos + IVec2::from(dir));
+        let next = pos + IVec2::from(dir);
+        state.owners[next.y as usize * state.width + next.x as usize].get_or_insert(n);
+        cursor.path.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn positions(count: usize, start: CursorStart, width: usize, height: usize) -> Vec<IVec2> {
+        let mut rng = StdRng::seed_from_u64(0);
+        CursorConfig { count, start }
+            .start_positions(width, height, &mut rng)
+            .unwrap()
+    }
+
+    fn is_distinct(positions: &[IVec2]) -> bool {
+        positions
+            .iter()
+            .enumerate()
+            .all(|(i, pos)| !positions[..i].contains(pos))
+    }
+
+    #[test]
+    fn test_start_positions() {
+        assert_eq!(
+            positions(4, CursorStart::Corners, 8, 6),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(7, 5),
+                IVec2::new(7, 0),
+                IVec2::new(0, 5)
+            ]
+        );
+        assert_eq!(
+            positions(4, CursorStart::Even, 8, 8),
+            [
+                IVec2::new(2, 2),
+                IVec2::new(6, 2),
+                IVec2::new(2, 6),
+                IVec2::new(6, 6)
+            ]
+        );
+
+        for start in [CursorStart::Random, CursorStart::Corners, CursorStart::Even] {
+            for (count, width, height) in [(1, 1, 1), (9, 8, 6), (100, 5, 4), (3, 1, 10)] {
+                let positions = positions(count, start.clone(), width, height);
+                assert!(is_distinct(&positions), "{:?}", start);
+                assert!(positions.len() <= count.min(width * height));
+                assert!(positions
+                    .iter()
+                    .all(|p| p.x < width as i32 && p.y < height as i32));
+                if start != CursorStart::Even {
+                    assert_eq!(positions.len(), count.min(width * height));
+                }
+            }
+        }
+
+        let start: CursorStart = "1,2; 3,0;1,2".parse().unwrap();
+        assert_eq!(
+            positions(0, start, 4, 4),
+            [IVec2::new(1, 2), IVec2::new(3, 0)]
+        );
+        assert_eq!("Even".parse(), Ok(CursorStart::Even));
+        assert!("1;2".parse::<CursorStart>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let validate = |count, start| CursorConfig { count, start }.validate(4, 3);
+        assert_eq!(validate(1, CursorStart::Random), Ok(()));
+        for start in [CursorStart::Random, CursorStart::Corners, CursorStart::Even] {
+            assert_eq!(validate(0, start), Err(CursorError::NoCursors));
+        }
+        assert_eq!(
+            validate(4, CursorStart::Positions(Vec::new())),
+            Err(CursorError::NoCursors)
+        );
+        assert_eq!(
+            validate(
+                4,
+                CursorStart::Positions(vec![IVec2::new(1, 1), IVec2::new(4, 0)])
+            ),
+            Err(CursorError::Outside {
+                pos: IVec2::new(4, 0),
+                width: 4,
+                height: 3
+            })
+        );
+        assert!(validate(0, CursorStart::Positions(vec![IVec2::new(-1, 2)])).is_err());
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let config = CursorConfig {
+            count: 0,
+            start: CursorStart::Corners,
+        };
+        assert!(init(4, 3, &config, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_many_cursors() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let config = CursorConfig {
+            count: 12,
+            start: CursorStart::Even,
+        };
+        let mut grid = Grid::with_size(12, 9);
+        let mut state = init(12, 9, &config, &mut rng).unwrap();
+        assert_eq!(state.cursors.len(), 12);
+
+        while grid.regions.num_sets() > 1 {
+            step(&mut state, &mut grid, &mut rng);
+        }
+        assert!(state.owners.iter().all(|owner| owner.is_some()));
+        let owners: Vec<usize> = state.owners.iter().flatten().copied().collect();
+        assert!((0..12).all(|n| owners.contains(&n)));
     }
 }
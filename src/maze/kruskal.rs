@@ -1,35 +1,177 @@
-use bevy::math::IVec2;
-use rand::seq::SliceRandom;
+use std::{fmt, path::Path, sync::Arc};
 
-use crate::{
-    consts::*,
-    grid::{Dir, Grid},
-};
+use bevy::math::{IVec2, Vec2};
+use image::GrayImage;
+use rand::{seq::SliceRandom, Rng};
+
+use super::ActiveCell;
+use crate::grid::{Dir, Grid};
+
+/// The weight of the wall between a cell and its neighbour towards `Dir`
+pub type WeightFn = dyn Fn(IVec2, Dir) -> f32 + Send + Sync;
+
+/// The order in which Kruskal's algorithm considers the walls. Walls with a
+/// lower weight are removed first, walls with the same weight in random order.
+#[derive(Clone, Default)]
+pub enum EdgeWeights {
+    /// All walls in random order
+    #[default]
+    Uniform,
+    Function(Arc<WeightFn>),
+    /// The brightness of the image, stretched over the grid, averaged over
+    /// the two cells of a wall. The top row of the image is the top of the
+    /// maze.
+    Image(Arc<GrayImage>),
+}
+
+impl EdgeWeights {
+    pub fn function(f: impl Fn(IVec2, Dir) -> f32 + Send + Sync + 'static) -> Self {
+        EdgeWeights::Function(Arc::new(f))
+    }
+
+    /// Random weights, with the walls between a cell and the one above it
+    /// `bias` heavier than the others, so that long horizontal corridors form
+    /// first. A negative `bias` prefers vertical corridors.
+    pub fn directional(bias: f32, seed: u64) -> Self {
+        EdgeWeights::function(move |pos, dir| {
+            let jitter = hash(pos, dir, seed);
+            match dir {
+                Dir::North | Dir::South => jitter + bias.max(0.0),
+                Dir::East | Dir::West => jitter - bias.min(0.0),
+            }
+        })
+    }
+
+    /// Smooth noise with features about `scale` cells wide, so that regions
+    /// grow out of its valleys
+    pub fn noise(scale: f32, seed: u64) -> Self {
+        EdgeWeights::function(move |pos, dir| {
+            let middle = pos.as_vec2() + 0.5 * IVec2::from(dir).as_vec2();
+            value_noise(middle / scale.max(f32::EPSILON), seed)
+        })
+    }
+
+    /// Weights from the brightness of an image file
+    pub fn from_image(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        Ok(EdgeWeights::Image(Arc::new(image::open(path)?.to_luma8())))
+    }
+
+    /// The weight of the wall between `pos` and its neighbour towards `dir`
+    /// in a `width`x`height` grid
+    pub fn weight(&self, pos: IVec2, dir: Dir, width: usize, height: usize) -> f32 {
+        match self {
+            EdgeWeights::Uniform => 0.0,
+            EdgeWeights::Function(f) => f(pos, dir),
+            EdgeWeights::Image(image) => {
+                let brightness = |pos: IVec2| {
+                    let scale = Vec2::new(
+                        image.width() as f32 / width as f32,
+                        image.height() as f32 / height as f32,
+                    );
+                    let pixel = ((pos.as_vec2() + 0.5) * scale).as_uvec2();
+                    let x = pixel.x.min(image.width() - 1);
+                    let y = image.height() - 1 - pixel.y.min(image.height() - 1);
+                    image.get_pixel(x, y).0[0] as f32 / 255.0
+                };
+                (brightness(pos) + brightness(pos + IVec2::from(dir))) / 2.0
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EdgeWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeWeights::Uniform => write!(f, "Uniform"),
+            EdgeWeights::Function(_) => write!(f, "Function"),
+            EdgeWeights::Image(image) => write!(f, "Image({}x{})", image.width(), image.height()),
+        }
+    }
+}
+
+impl PartialEq for EdgeWeights {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EdgeWeights::Uniform, EdgeWeights::Uniform) => true,
+            (EdgeWeights::Function(a), EdgeWeights::Function(b)) => Arc::ptr_eq(a, b),
+            (EdgeWeights::Image(a), EdgeWeights::Image(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A number in `0.0..1.0` which looks random but only depends on the
+/// arguments
+fn hash(pos: IVec2, dir: Dir, seed: u64) -> f32 {
+    let mut h = seed ^ 0x9e37_79b9_7f4a_7c15;
+    for n in [pos.x as u64, pos.y as u64, dir as u64] {
+        h = (h ^ n).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h ^= h >> 31;
+    }
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Noise in `0.0..1.0`, interpolating random values at integer positions
+fn value_noise(pos: Vec2, seed: u64) -> f32 {
+    let cell = pos.floor();
+    let t = pos - cell;
+    let t = t * t * (3.0 - 2.0 * t);
+    let corner = |x, y| hash(cell.as_ivec2() + IVec2::new(x, y), Dir::North, seed);
+    let bottom = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * t.x;
+    let top = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * t.x;
+    bottom + (top - bottom) * t.y
+}
 
 pub struct MazeState {
     queue: Vec<(IVec2, Dir)>,
 }
 
-pub fn init() -> MazeState {
+pub fn init(width: usize, height: usize, weights: &EdgeWeights, rng: &mut impl Rng) -> MazeState {
+    let w = width as i32;
+    let h = height as i32;
     let mut queue = Vec::new();
-    for y in 0..GRID_HEIGHT as i32 {
-        for x in 0..GRID_WIDTH as i32 {
-            if y < GRID_HEIGHT as i32 - 1 {
+    for y in 0..h {
+        for x in 0..w {
+            if y < h - 1 {
                 queue.push((IVec2::new(x, y), Dir::North));
             }
 
-            if x < GRID_WIDTH as i32 - 1 {
+            if x < w - 1 {
                 queue.push((IVec2::new(x, y), Dir::East));
             }
         }
     }
 
-    let mut rng = rand::thread_rng();
-    queue.shuffle(&mut rng);
+    queue.shuffle(rng);
+
+    // The sort is stable, so walls with the same weight stay shuffled. The
+    // queue is popped from the back, so the heaviest walls go first.
+    if !matches!(weights, EdgeWeights::Uniform) {
+        let mut weighted: Vec<(f32, (IVec2, Dir))> = queue
+            .into_iter()
+            .map(|(pos, dir)| (weights.weight(pos, dir, width, height), (pos, dir)))
+            .collect();
+        weighted.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        queue = weighted.into_iter().map(|(_, wall)| wall).collect();
+    }
 
     MazeState { queue }
 }
 
+/// The two cells of the next wall in the queue
+pub fn active_cells(state: &MazeState) -> Vec<(IVec2, ActiveCell)> {
+    state
+        .queue
+        .last()
+        .map(|(pos, dir)| {
+            vec![
+                (*pos, ActiveCell::Frontier),
+                (*pos + IVec2::from(*dir), ActiveCell::Frontier),
+            ]
+        })
+        .unwrap_or_default()
+}
+
 pub fn step(state: &mut MazeState, grid: &mut Grid) {
     loop {
         let (pos, dir) = state
@@ -42,3 +184,112 @@ pub fn step(state: &mut MazeState, grid: &mut Grid) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn generate(weights: &EdgeWeights, size: usize, seed: u64) -> Grid {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid = Grid::with_size(size, size);
+        let mut state = init(size, size, weights, &mut rng);
+        while grid.regions.num_sets() > 1 {
+            step(&mut state, &mut grid);
+        }
+        grid
+    }
+
+    fn num_open(grid: &Grid, dir: Dir) -> usize {
+        grid.positions()
+            .filter(|pos| grid.open_dirs(*pos).any(|d| d == dir))
+            .count()
+    }
+
+    #[test]
+    fn test_order() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let weights = EdgeWeights::function(|pos, dir| match dir {
+            Dir::East => pos.x as f32,
+            _ => 100.0,
+        });
+        let state = init(4, 3, &weights, &mut rng);
+
+        let order: Vec<f32> = state
+            .queue
+            .iter()
+            .rev()
+            .map(|(pos, dir)| weights.weight(*pos, *dir, 4, 3))
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_horizontal() {
+        // Every horizontal wall is removed before any vertical one, so each
+        // row is one corridor joined to the next by a single opening
+        let weights = EdgeWeights::function(|_, dir| if dir == Dir::East { 0.0 } else { 1.0 });
+        let grid = generate(&weights, 8, 0);
+        assert_eq!(num_open(&grid, Dir::East), 8 * 7);
+        assert_eq!(num_open(&grid, Dir::North), 7);
+
+        // Ties are broken randomly
+        let other = generate(&weights, 8, 1);
+        assert_ne!(grid.walls(), other.walls());
+    }
+
+    #[test]
+    fn test_presets() {
+        let grid = generate(&EdgeWeights::directional(0.5, 0), 16, 0);
+        assert!(num_open(&grid, Dir::East) > 2 * num_open(&grid, Dir::North));
+        let grid = generate(&EdgeWeights::directional(-0.5, 0), 16, 0);
+        assert!(num_open(&grid, Dir::North) > 2 * num_open(&grid, Dir::East));
+
+        for seed in 0..4 {
+            let weights = EdgeWeights::noise(4.0, seed);
+            let grid = generate(&weights, 16, seed);
+            assert_eq!(grid.regions.num_sets(), 1);
+            assert!(grid
+                .positions()
+                .all(|pos| (0.0..1.0).contains(&weights.weight(pos, Dir::East, 16, 16))));
+        }
+
+        // Nearby walls get similar weights
+        let weights = EdgeWeights::noise(8.0, 0);
+        let a = weights.weight(IVec2::new(3, 3), Dir::East, 16, 16);
+        let b = weights.weight(IVec2::new(3, 3), Dir::North, 16, 16);
+        assert!((a - b).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_image() {
+        // A dark left half and a bright right half, which is only joined to
+        // the left half once it is done
+        let image = GrayImage::from_fn(16, 4, |x, _| Luma([if x < 8 { 0 } else { 255 }]));
+        let weights = EdgeWeights::Image(Arc::new(image));
+        assert_eq!(weights.weight(IVec2::new(0, 0), Dir::North, 8, 8), 0.0);
+        assert_eq!(weights.weight(IVec2::new(3, 0), Dir::East, 8, 8), 0.5);
+        assert_eq!(weights.weight(IVec2::new(7, 7), Dir::East, 8, 8), 1.0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut grid = Grid::with_size(8, 8);
+        let mut state = init(8, 8, &weights, &mut rng);
+        for _ in 0..31 {
+            step(&mut state, &mut grid);
+        }
+        let left = grid.region(IVec2::new(0, 0));
+        assert!((0..8)
+            .flat_map(|y| (0..4).map(move |x| IVec2::new(x, y)))
+            .all(|pos| grid.region(pos) == left));
+    }
+
+    #[test]
+    fn test_image_orientation() {
+        let image = GrayImage::from_fn(2, 2, |_, y| Luma([if y == 0 { 255 } else { 0 }]));
+        let weights = EdgeWeights::Image(Arc::new(image));
+        assert_eq!(weights.weight(IVec2::new(0, 1), Dir::East, 2, 2), 1.0);
+        assert_eq!(weights.weight(IVec2::new(0, 0), Dir::East, 2, 2), 0.0);
+    }
+}
@@ -1,50 +1,63 @@
-use bevy::math::IVec2;
+use bevy::log::debug;
 
-use crate::consts::*;
+use super::ActiveCell;
 use crate::grid::Grid;
+use bevy::math::IVec2;
 use rand::Rng;
 
 pub struct MazeState {
     fixed: Vec<bool>,
+    /// The cells with the fewest possible moves in the last step
+    candidates: Vec<IVec2>,
+    /// The candidate picked in the last step
+    chosen: Option<IVec2>,
 }
 
-pub fn init() -> MazeState {
+pub fn init(width: usize, height: usize) -> MazeState {
     MazeState {
-        fixed: vec![false; GRID_WIDTH * GRID_HEIGHT],
+        fixed: vec![false; width * height],
+        candidates: Vec::new(),
+        chosen: None,
     }
 }
 
-pub fn step(state: &mut MazeState, grid: &mut Grid) {
+pub fn active_cells(state: &MazeState) -> Vec<(IVec2, ActiveCell)> {
+    state
+        .candidates
+        .iter()
+        .map(|pos| (*pos, ActiveCell::Candidate))
+        .chain(state.chosen.map(|pos| (pos, ActiveCell::Head(0))))
+        .collect()
+}
+
+pub fn step(state: &mut MazeState, grid: &mut Grid, rng: &mut impl Rng) {
     let mut tiles = Vec::new();
 
     let mut min_len = 4;
-    for y in 0..GRID_HEIGHT {
-        for x in 0..GRID_WIDTH {
-            let i = y * GRID_WIDTH + x;
-            let m = grid.possible_moves(IVec2::new(x as i32, y as i32));
-
-            if !state.fixed[i] && !m.is_empty() && m.len() <= min_len {
-                min_len = min_len.min(m.len());
-                tiles.push((i, m));
-            }
+    for i in 0..grid.num_cells() {
+        let m = grid.possible_moves(grid.position(i));
+
+        if !state.fixed[i] && !m.is_empty() && m.len() <= min_len {
+            min_len = min_len.min(m.len());
+            tiles.push((i, m));
         }
     }
     tiles.retain(|(_, v)| v.len() == min_len);
 
+    state.candidates = tiles.iter().map(|(i, _)| grid.position(*i)).collect();
+    state.chosen = None;
+
     if tiles.is_empty() {
-        println!("Tile list is empty. Retrying...");
+        debug!("Tile list is empty. Retrying...");
         state.fixed.fill(false);
         return;
     }
 
-    let mut rng = rand::thread_rng();
-
     let tile_index = rng.gen_range(0..tiles.len());
     let (min_index, min_moves) = &tiles[tile_index];
 
-    let min_y = min_index / GRID_WIDTH;
-    let min_x = min_index % GRID_WIDTH;
-    let pos = IVec2::new(min_x as i32, min_y as i32);
+    let pos = grid.position(*min_index);
+    state.chosen = Some(pos);
 
     for dir in min_moves {
         if rng.gen_bool(0.5) {
@@ -28,7 +28,9 @@ impl TilemapMaterialShader for OverlayShader {
 }
 
 impl TilemapData for Overlay {
-    fn data(&self) -> &Vec<u8> {
+    type Item = u8;
+
+    fn data(&self) -> &[u8] {
         &self.data
     }
 
@@ -0,0 +1,351 @@
+//! Rendering of mazes to SVG and PNG for printing. This does not use the
+//! tilemaps or the GPU, so it also works without a window.
+
+use std::{fmt::Write, io::Cursor};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::*,
+    consts::*,
+    grid::{Dir, Grid},
+};
+
+const WALL_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const BACKGROUND_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const SOLUTION_COLOR: [u8; 4] = [0x31, 0x99, 0x6f, 0xFF];
+const START_COLOR: [u8; 4] = [0x20, 0x60, 0xD0, 0xFF];
+const GOAL_COLOR: [u8; 4] = [0xD0, 0x30, 0x30, 0xFF];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintOptions {
+    pub cell_size: u32,
+    pub line_width: u32,
+    pub solution: Option<Vec<IVec2>>,
+    pub start: Option<IVec2>,
+    pub goal: Option<IVec2>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 32,
+            line_width: 2,
+            solution: None,
+            start: None,
+            goal: None,
+        }
+    }
+}
+
+/// An axis aligned rectangle in pixels, with the origin in the top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, export);
+}
+
+impl PrintOptions {
+    fn image_size(&self, grid: &Grid) -> UVec2 {
+        UVec2::new(
+            grid.width() as u32 * self.cell_size + self.line_width,
+            grid.height() as u32 * self.cell_size + self.line_width,
+        )
+    }
+
+    /// The top left corner of a cell. The grid has y pointing up, while the
+    /// image has y pointing down.
+    fn corner(&self, grid: &Grid, pos: IVec2) -> UVec2 {
+        UVec2::new(
+            pos.x as u32 * self.cell_size,
+            (grid.height() as u32 - 1 - pos.y as u32) * self.cell_size,
+        )
+    }
+
+    fn center(&self, grid: &Grid, pos: IVec2) -> Vec2 {
+        self.corner(grid, pos).as_vec2()
+            + Vec2::splat((self.cell_size + self.line_width) as f32 / 2.0)
+    }
+
+    fn path_width(&self) -> u32 {
+        (self.cell_size / 4).max(self.line_width)
+    }
+
+    fn marker_radius(&self) -> f32 {
+        self.cell_size as f32 / 3.0
+    }
+
+    fn walls(&self, grid: &Grid) -> Vec<Rect> {
+        let mut walls = vec![];
+        let len = self.cell_size + self.line_width;
+
+        for pos in grid.positions() {
+            let corner = self.corner(grid, pos);
+
+            if pos.y == grid.height() as i32 - 1 && grid.has_wall(pos, Dir::North) {
+                walls.push(Rect {
+                    x: corner.x,
+                    y: corner.y,
+                    w: len,
+                    h: self.line_width,
+                });
+            }
+            if grid.has_wall(pos, Dir::South) {
+                walls.push(Rect {
+                    x: corner.x,
+                    y: corner.y + self.cell_size,
+                    w: len,
+                    h: self.line_width,
+                });
+            }
+            if grid.has_wall(pos, Dir::West) {
+                walls.push(Rect {
+                    x: corner.x,
+                    y: corner.y,
+                    w: self.line_width,
+                    h: len,
+                });
+            }
+            if pos.x == grid.width() as i32 - 1 && grid.has_wall(pos, Dir::East) {
+                walls.push(Rect {
+                    x: corner.x + self.cell_size,
+                    y: corner.y,
+                    w: self.line_width,
+                    h: len,
+                });
+            }
+        }
+
+        walls
+    }
+
+    fn solution(&self, grid: &Grid) -> Vec<Rect> {
+        let Some(path) = &self.solution else {
+            return vec![];
+        };
+
+        let w = self.path_width() as f32;
+        path.windows(2)
+            .map(|p| {
+                let a = self.center(grid, p[0]);
+                let b = self.center(grid, p[1]);
+                let min = a.min(b) - w / 2.0;
+                let max = a.max(b) + w / 2.0;
+                Rect {
+                    x: min.x.round() as u32,
+                    y: min.y.round() as u32,
+                    w: (max.x - min.x).round() as u32,
+                    h: (max.y - min.y).round() as u32,
+                }
+            })
+            .collect()
+    }
+
+    fn markers(&self, grid: &Grid) -> Vec<(Vec2, [u8; 4])> {
+        [(self.start, START_COLOR), (self.goal, GOAL_COLOR)]
+            .into_iter()
+            .filter_map(|(pos, color)| Some((self.center(grid, pos?), color)))
+            .collect()
+    }
+}
+
+fn svg_color(color: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn svg_rect(svg: &mut String, rect: Rect, color: [u8; 4]) {
+    writeln!(
+        svg,
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        svg_color(color)
+    )
+    .unwrap();
+}
+
+pub fn to_svg(grid: &Grid, options: &PrintOptions) -> String {
+    let size = options.image_size(grid);
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" shape-rendering="crispEdges">"#,
+        size.x, size.y
+    )
+    .unwrap();
+    svg_rect(
+        &mut svg,
+        Rect {
+            x: 0,
+            y: 0,
+            w: size.x,
+            h: size.y,
+        },
+        BACKGROUND_COLOR,
+    );
+
+    for rect in options.solution(grid) {
+        svg_rect(&mut svg, rect, SOLUTION_COLOR);
+    }
+
+    for (center, color) in options.markers(grid) {
+        writeln!(
+            svg,
+            r#"  <circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            center.x,
+            center.y,
+            options.marker_radius(),
+            svg_color(color)
+        )
+        .unwrap();
+    }
+
+    for rect in options.walls(grid) {
+        svg_rect(&mut svg, rect, WALL_COLOR);
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Rasterise the maze into an RGBA8 image of size `options.image_size(grid)`
+pub fn to_rgba(grid: &Grid, options: &PrintOptions) -> image::RgbaImage {
+    let size = options.image_size(grid);
+    let mut image = image::RgbaImage::from_pixel(size.x, size.y, image::Rgba(BACKGROUND_COLOR));
+
+    let mut fill = |rect: Rect, color: [u8; 4]| {
+        for y in rect.y..(rect.y + rect.h).min(size.y) {
+            for x in rect.x..(rect.x + rect.w).min(size.x) {
+                image.put_pixel(x, y, image::Rgba(color));
+            }
+        }
+    };
+
+    for rect in options.solution(grid) {
+        fill(rect, SOLUTION_COLOR);
+    }
+
+    let r = options.marker_radius();
+    for (center, color) in options.markers(grid) {
+        let bounds = Rect {
+            x: (center.x - r).floor() as u32,
+            y: (center.y - r).floor() as u32,
+            w: (2.0 * r).ceil() as u32 + 1,
+            h: (2.0 * r).ceil() as u32 + 1,
+        };
+        for y in bounds.y..bounds.y + bounds.h {
+            for x in bounds.x..bounds.x + bounds.w {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if p.distance(center) <= r {
+                    fill(Rect { x, y, w: 1, h: 1 }, color);
+                }
+            }
+        }
+    }
+
+    for rect in options.walls(grid) {
+        fill(rect, WALL_COLOR);
+    }
+
+    image
+}
+
+pub fn to_png(grid: &Grid, options: &PrintOptions) -> Vec<u8> {
+    let mut png = vec![];
+    to_rgba(grid, options)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .expect("Encoding a PNG in memory should not fail");
+    png
+}
+
+fn export(grid_query: Query<&Grid, With<Trees>>, input: Res<ButtonInput<KeyCode>>) {
+    if !input.just_pressed(KeyCode::F3) {
+        return;
+    }
+
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    let options = PrintOptions {
+        start: Some(IVec2::ZERO),
+        goal: Some(IVec2::new(GRID_WIDTH as i32 - 1, GRID_HEIGHT as i32 - 1)),
+        ..default()
+    };
+
+    for (path, data) in [
+        ("maze.svg", to_svg(grid, &options).into_bytes()),
+        ("maze.png", to_png(grid, &options)),
+    ] {
+        match std::fs::write(path, data) {
+            Ok(()) => info!("Exported maze to {}", path),
+            Err(err) => error!("Could not export maze: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver;
+
+    fn grid() -> Grid {
+        let mut grid = Grid::with_size(2, 2);
+        grid.remove_wall(IVec2::new(0, 0), Dir::North).unwrap();
+        grid.remove_wall(IVec2::new(0, 1), Dir::East).unwrap();
+        grid.remove_wall(IVec2::new(1, 1), Dir::South).unwrap();
+        grid
+    }
+
+    #[test]
+    fn test_svg() {
+        let grid = grid();
+        let options = PrintOptions {
+            solution: solver::solve(&grid, IVec2::ZERO, IVec2::new(1, 0)),
+            start: Some(IVec2::ZERO),
+            goal: Some(IVec2::new(1, 0)),
+            ..default()
+        };
+        let svg = to_svg(&grid, &options);
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"width="66" height="66""#));
+        // Background, three path segments and nine walls
+        assert_eq!(svg.matches("<rect").count(), 1 + 3 + 9);
+        assert_eq!(svg.matches("<circle").count(), 2);
+    }
+
+    #[test]
+    fn test_png() {
+        let grid = grid();
+        let options = PrintOptions {
+            cell_size: 10,
+            line_width: 2,
+            ..default()
+        };
+        let image = to_rgba(&grid, &options);
+
+        assert_eq!(image.dimensions(), (22, 22));
+        // The outer walls
+        assert_eq!(image.get_pixel(0, 0).0, WALL_COLOR);
+        assert_eq!(image.get_pixel(21, 21).0, WALL_COLOR);
+        // The wall between (0, 0) and (1, 0)
+        assert_eq!(image.get_pixel(10, 16).0, WALL_COLOR);
+        // The opening between (0, 0) and (0, 1)
+        assert_eq!(image.get_pixel(6, 10).0, BACKGROUND_COLOR);
+        // Cell centres
+        assert_eq!(image.get_pixel(6, 6).0, BACKGROUND_COLOR);
+
+        let png = to_png(&grid, &options);
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,394 @@
+//! Saving and loading of levels. Levels can be stored either as compact
+//! binary quick saves or as JSON for use with external tools.
+
+use std::{fmt, io, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::*,
+    consts::*,
+    cover::Cover,
+    grid::Grid,
+    history::{History, Replay},
+    maze::{GenerationTask, Level, MazeState, MazeType},
+    states::GamePlayState,
+};
+
+pub const VERSION: u32 = 2;
+const MAGIC: &[u8; 4] = b"MAZE";
+
+const QUICK_SAVE_PATH: &str = "quicksave.bin";
+const JSON_PATH: &str = "level.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    pub walls: Vec<u8>,
+    pub seed: u64,
+    pub maze_type: MazeType,
+    pub braid: f32,
+    pub player: [f32; 2],
+    pub goal: [f32; 2],
+    pub elapsed: f32,
+    /// Whether each cell is `cover::COVERED` or `cover::REVEALED`
+    pub cover: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    NotASave,
+    Version(u32),
+    Size,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{err}"),
+            SaveError::Json(err) => write!(f, "invalid JSON: {err}"),
+            SaveError::Binary(err) => write!(f, "invalid binary data: {err}"),
+            SaveError::NotASave => write!(f, "not a saved level"),
+            SaveError::Version(version) => write!(
+                f,
+                "unsupported version {version}, expected version {VERSION}"
+            ),
+            SaveError::Size => write!(
+                f,
+                "the level is not {GRID_WIDTH}x{GRID_HEIGHT} or does not match its data"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveError::Json(err)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(err: bincode::Error) -> Self {
+        SaveError::Binary(err)
+    }
+}
+
+/// A level waiting to be loaded into the world
+#[derive(Resource)]
+pub struct PendingLoad(pub SaveData);
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, save.run_if(in_state(GamePlayState::Playing)))
+        .add_systems(Update, (quick_load, apply_load).chain());
+}
+
+impl SaveData {
+    /// A level which has not been played yet, with the player in the bottom
+    /// left corner and the goal in the top right corner
+    pub fn new(grid: &Grid, level: &Level) -> Self {
+        Self {
+            version: VERSION,
+            width: grid.width(),
+            height: grid.height(),
+            walls: grid.walls().to_vec(),
+            seed: level.seed,
+            maze_type: level.maze_type,
+            braid: level.braid,
+            player: [0.5, 0.5],
+            goal: [grid.width() as f32 - 0.5, grid.height() as f32 - 0.5],
+            elapsed: 0.0,
+            cover: vec![0; grid.num_cells()],
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, SaveError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SaveError> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let header: Header = serde_json::from_str(json)?;
+        if header.version != VERSION {
+            return Err(SaveError::Version(header.version));
+        }
+
+        let data: SaveData = serde_json::from_str(json)?;
+        data.validate()?;
+        Ok(data)
+    }
+
+    /// The binary format is the magic bytes `MAZE`, followed by the version as
+    /// a little endian `u32` and the bincode encoded data
+    pub fn to_binary(&self) -> Result<Vec<u8>, SaveError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self)?);
+        Ok(bytes)
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, SaveError> {
+        let Some((magic, bytes)) = bytes.split_first_chunk::<4>() else {
+            return Err(SaveError::NotASave);
+        };
+        if magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+
+        let Some((version, bytes)) = bytes.split_first_chunk::<4>() else {
+            return Err(SaveError::NotASave);
+        };
+        let version = u32::from_le_bytes(*version);
+        if version != VERSION {
+            return Err(SaveError::Version(version));
+        }
+
+        let data: SaveData = bincode::deserialize(bytes)?;
+        data.validate()?;
+        Ok(data)
+    }
+
+    /// Load a file, using the binary format unless the extension is `json`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json(&std::fs::read_to_string(path)?)
+        } else {
+            Self::from_binary(&std::fs::read(path)?)
+        }
+    }
+
+    pub fn grid(&self) -> Grid {
+        Grid::from_walls(self.width, self.height, &self.walls)
+    }
+
+    /// Check the data before anything is loaded into the world, which only
+    /// holds mazes of `GRID_WIDTH` by `GRID_HEIGHT` cells
+    fn validate(&self) -> Result<(), SaveError> {
+        if self.width != GRID_WIDTH
+            || self.height != GRID_HEIGHT
+            || self.walls.len() != self.width * self.height
+            || self.cover.len() != self.width * self.height
+        {
+            return Err(SaveError::Size);
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn save(
+    grid_query: Query<&Grid, With<Trees>>,
+    cover_query: Query<&Cover>,
+    player_query: Query<&Transform, (With<Player>, Without<Goal>)>,
+    goal_query: Query<&Transform, (With<Goal>, Without<Player>)>,
+    level: Res<Level>,
+    elapsed: Res<ElapsedTime>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    let binary = input.just_pressed(KeyCode::F5);
+    let json = input.just_pressed(KeyCode::F6);
+    if !binary && !json {
+        return;
+    }
+
+    let (Ok(grid), Ok(cover), Ok(player), Ok(goal)) = (
+        grid_query.get_single(),
+        cover_query.get_single(),
+        player_query.get_single(),
+        goal_query.get_single(),
+    ) else {
+        return;
+    };
+
+    let data = SaveData {
+        player: player.translation.xy().into(),
+        goal: goal.translation.xy().into(),
+        elapsed: elapsed.0,
+        cover: cover.to_saved(),
+        ..SaveData::new(grid, &level)
+    };
+
+    let (path, result) = if binary {
+        (QUICK_SAVE_PATH, data.to_binary())
+    } else {
+        (JSON_PATH, data.to_json().map(String::into_bytes))
+    };
+
+    match result.and_then(|bytes| Ok(std::fs::write(path, bytes)?)) {
+        Ok(()) => info!("Saved level to {}", path),
+        Err(err) => error!("Could not save level: {}", err),
+    }
+}
+
+fn quick_load(mut commands: Commands, input: Res<ButtonInput<KeyCode>>) {
+    if !input.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    match SaveData::load(QUICK_SAVE_PATH) {
+        Ok(data) => commands.insert_resource(PendingLoad(data)),
+        Err(err) => error!("Could not load level: {}", err),
+    }
+}
+
+/// Replace the current level with the pending one. The player and goal are
+/// only spawned when entering `GamePlayState::Playing`, so if the maze is
+/// still being generated the generation is stopped and the load finishes on a
+/// later frame.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn apply_load(
+    mut commands: Commands,
+    pending: Option<Res<PendingLoad>>,
+    mut grid_query: Query<&mut Grid, With<Trees>>,
+    mut cover_query: Query<&mut Cover>,
+    mut history: ResMut<History>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<Goal>)>,
+    mut goal_query: Query<&mut Transform, (With<Goal>, Without<Player>)>,
+    state_query: Query<Entity, Or<(With<MazeState>, With<GenerationTask>)>>,
+    mut next_state: ResMut<NextState<GamePlayState>>,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+    let data = &pending.0;
+
+    let (Ok(mut grid), Ok(mut cover)) = (grid_query.get_single_mut(), cover_query.get_single_mut())
+    else {
+        return;
+    };
+
+    *grid = data.grid();
+    cover.load(&data.cover);
+    history.clear();
+    commands.remove_resource::<Replay>();
+    commands.insert_resource(Level {
+        maze_type: data.maze_type,
+        seed: data.seed,
+        braid: data.braid,
+    });
+    commands.insert_resource(ElapsedTime(data.elapsed));
+
+    for entity in state_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let (Ok(mut player), Ok(mut goal)) =
+        (player_query.get_single_mut(), goal_query.get_single_mut())
+    else {
+        next_state.set(GamePlayState::Playing);
+        return;
+    };
+
+    player.translation = Vec2::from(data.player).extend(player.translation.z);
+    goal.translation = Vec2::from(data.goal).extend(goal.translation.z);
+
+    commands.remove_resource::<PendingLoad>();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::maze;
+
+    fn data() -> SaveData {
+        let mut rng = StdRng::seed_from_u64(1);
+        let grid = maze::generate_grid(MazeType::Kruskal, GRID_WIDTH, GRID_HEIGHT, &mut rng);
+
+        SaveData {
+            version: VERSION,
+            width: grid.width(),
+            height: grid.height(),
+            walls: grid.walls().to_vec(),
+            seed: 1,
+            maze_type: MazeType::Kruskal,
+            braid: 0.0,
+            player: [0.5, 0.5],
+            goal: [4.5, 3.5],
+            elapsed: 12.5,
+            cover: (0..grid.num_cells()).map(|i| i as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn test_binary() {
+        let data = data();
+        let bytes = data.to_binary().unwrap();
+        assert_eq!(SaveData::from_binary(&bytes).unwrap(), data);
+    }
+
+    #[test]
+    fn test_json() {
+        let data = data();
+        let json = data.to_json().unwrap();
+        assert_eq!(SaveData::from_json(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn test_grid() {
+        let data = data();
+        let grid = data.grid();
+        assert_eq!(grid.walls(), &data.walls[..]);
+        assert_eq!(grid.regions.num_sets(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut data = data();
+
+        let mut bytes = data.to_binary().unwrap();
+        bytes[4] = 99;
+        assert!(matches!(
+            SaveData::from_binary(&bytes),
+            Err(SaveError::Version(99))
+        ));
+        assert!(matches!(
+            SaveData::from_binary(b"PNG"),
+            Err(SaveError::NotASave)
+        ));
+
+        data.version = 0;
+        let json = data.to_json().unwrap();
+        assert!(matches!(
+            SaveData::from_json(&json),
+            Err(SaveError::Version(0))
+        ));
+
+        data.version = VERSION;
+        data.cover.pop();
+        let json = data.to_json().unwrap();
+        assert!(matches!(SaveData::from_json(&json), Err(SaveError::Size)));
+
+        // Mazes of other sizes can not be loaded into the world
+        for (width, height) in [(5, 4), (GRID_WIDTH, 1)] {
+            data.width = width;
+            data.height = height;
+            data.walls = vec![0; width * height];
+            data.cover = vec![0; width * height];
+            let bytes = data.to_binary().unwrap();
+            assert!(matches!(
+                SaveData::from_binary(&bytes),
+                Err(SaveError::Size)
+            ));
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+
+use bevy::math::IVec2;
+
+use crate::grid::{Dir, Grid};
+
+/// Breadth first search from `start`, returning the distance to every cell
+/// reachable from it and the cell it was reached from.
+fn search(grid: &Grid, start: IVec2) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+    let mut dist = vec![None; grid.num_cells()];
+    let mut prev = vec![None; grid.num_cells()];
+    let mut queue = VecDeque::new();
+
+    dist[grid.index(start)] = Some(0);
+    queue.push_back(start);
+
+    while let Some(pos) = queue.pop_front() {
+        let d = dist[grid.index(pos)].unwrap();
+        for dir in Dir::ALL {
+            if grid.has_wall(pos, dir) {
+                continue;
+            }
+            let next = pos + IVec2::from(dir);
+            if dist[grid.index(next)].is_none() {
+                dist[grid.index(next)] = Some(d + 1);
+                prev[grid.index(next)] = Some(grid.index(pos));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    (dist, prev)
+}
+
+/// The number of steps needed to reach every cell from `start`, or `None` if
+/// the cell cannot be reached.
+pub fn distances(grid: &Grid, start: IVec2) -> Vec<Option<usize>> {
+    search(grid, start).0
+}
+
+/// Find the shortest path from `start` to `goal`, including both end points.
+pub fn solve(grid: &Grid, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+    let (_, prev) = search(grid, start);
+
+    let mut path = vec![goal];
+    let mut i = grid.index(goal);
+    while i != grid.index(start) {
+        i = prev[i]?;
+        path.push(grid.position(i));
+    }
+    path.reverse();
+
+    Some(path)
+}
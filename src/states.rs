@@ -15,6 +15,8 @@ pub enum GamePlayState {
     #[default]
     GeneratingMaze,
     Playing,
+    /// Changing the walls and markers of the current maze by hand
+    Editing,
     LevelDone,
 }
 
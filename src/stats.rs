@@ -0,0 +1,357 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    components::*,
+    consts::*,
+    grid::{Dir, Grid},
+    maze::{self, MazeType},
+    overlay::Overlay,
+    solver,
+    states::GamePlayState,
+};
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct MazeStats {
+    pub cells: usize,
+    pub dead_ends: usize,
+    pub junctions: usize,
+    pub corridors: usize,
+    pub turns: usize,
+    pub solution_length: usize,
+    pub solution_ratio: f32,
+    pub longest_corridor: usize,
+    /// The average number of cells in a dead end branch, counted from the dead
+    /// end up to (but not including) the first junction. Mazes with a high
+    /// river factor have few but long dead ends.
+    pub river: f32,
+    pub diameter: usize,
+}
+
+#[derive(Component)]
+struct StatsText;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Startup, setup)
+        .add_systems(OnEnter(GamePlayState::Playing), compute)
+        .add_systems(Update, (update_text, visibility));
+}
+
+impl MazeStats {
+    pub fn new(grid: &Grid, start: IVec2, goal: IVec2) -> Self {
+        let cells = grid.num_cells();
+
+        let mut stats = MazeStats { cells, ..default() };
+
+        for pos in grid.positions() {
+            match grid.open_dirs(pos).count() {
+                1 => stats.dead_ends += 1,
+                2 if is_straight(grid, pos) => stats.corridors += 1,
+                2 => stats.turns += 1,
+                3.. => stats.junctions += 1,
+                _ => {}
+            }
+        }
+
+        if let Some(path) = solver::solve(grid, start, goal) {
+            stats.solution_length = path.len();
+            stats.solution_ratio = path.len() as f32 / cells as f32;
+        }
+
+        stats.longest_corridor = longest_corridor(grid);
+        stats.river = river(grid);
+        stats.diameter = diameter(grid);
+
+        stats
+    }
+}
+
+impl fmt::Display for MazeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Dead ends: {}", self.dead_ends)?;
+        writeln!(f, "Junctions: {}", self.junctions)?;
+        writeln!(f, "Corridors: {}", self.corridors)?;
+        writeln!(f, "Turns: {}", self.turns)?;
+        writeln!(
+            f,
+            "Solution: {} ({:.1}%)",
+            self.solution_length,
+            self.solution_ratio * 100.0
+        )?;
+        writeln!(f, "Longest corridor: {}", self.longest_corridor)?;
+        writeln!(f, "River: {:.2}", self.river)?;
+        write!(f, "Diameter: {}", self.diameter)
+    }
+}
+
+fn is_straight(grid: &Grid, pos: IVec2) -> bool {
+    let walls = grid.get_walls(pos);
+    walls == Dir::North as u8 | Dir::South as u8 || walls == Dir::East as u8 | Dir::West as u8
+}
+
+fn longest_corridor(grid: &Grid) -> usize {
+    let mut longest = 0;
+
+    for y in 0..grid.height() as i32 {
+        let mut run = 1;
+        for x in 0..grid.width() as i32 {
+            if grid.has_wall(IVec2::new(x, y), Dir::East) {
+                longest = longest.max(run);
+                run = 1;
+            } else {
+                run += 1;
+            }
+        }
+    }
+
+    for x in 0..grid.width() as i32 {
+        let mut run = 1;
+        for y in 0..grid.height() as i32 {
+            if grid.has_wall(IVec2::new(x, y), Dir::North) {
+                longest = longest.max(run);
+                run = 1;
+            } else {
+                run += 1;
+            }
+        }
+    }
+
+    longest
+}
+
+fn river(grid: &Grid) -> f32 {
+    let mut dead_ends = 0;
+    let mut total = 0;
+
+    for mut pos in grid.positions() {
+        let dirs: Vec<_> = grid.open_dirs(pos).collect();
+        let [mut dir] = dirs[..] else {
+            continue;
+        };
+
+        dead_ends += 1;
+        total += 1;
+
+        loop {
+            pos += IVec2::from(dir);
+            let dirs: Vec<_> = grid
+                .open_dirs(pos)
+                .filter(|d| *d != dir.reverse())
+                .collect();
+            let [next] = dirs[..] else {
+                // Either a junction or the other end of an unbranched maze
+                if dirs.is_empty() {
+                    total += 1;
+                }
+                break;
+            };
+            total += 1;
+            dir = next;
+        }
+    }
+
+    if dead_ends == 0 {
+        0.0
+    } else {
+        total as f32 / dead_ends as f32
+    }
+}
+
+fn farthest(grid: &Grid, start: IVec2) -> (IVec2, usize) {
+    let dist = solver::distances(grid, start);
+    let (i, d) = dist
+        .iter()
+        .enumerate()
+        .filter_map(|(i, d)| d.map(|d| (i, d)))
+        .max_by_key(|(_, d)| *d)
+        .unwrap();
+    (grid.position(i), d)
+}
+
+/// The length of the longest path in the maze. Since a perfect maze is a tree
+/// this is the distance to the cell farthest away from the cell which is
+/// farthest away from an arbitrary starting cell.
+fn diameter(grid: &Grid) -> usize {
+    let (end, _) = farthest(grid, IVec2::ZERO);
+    let (_, d) = farthest(grid, end);
+    d
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(MARGIN),
+            left: Val::Px(MARGIN),
+            ..default()
+        }),
+        StatsText,
+        Name::from("Stats"),
+    ));
+}
+
+fn compute(mut commands: Commands, grid_query: Query<(Entity, &Grid), With<Trees>>) {
+    let Ok((entity, grid)) = grid_query.get_single() else {
+        return;
+    };
+
+    let stats = MazeStats::new(
+        grid,
+        IVec2::ZERO,
+        IVec2::new(GRID_WIDTH as i32 - 1, GRID_HEIGHT as i32 - 1),
+    );
+    commands.entity(entity).insert(stats);
+}
+
+fn update_text(
+    stats_query: Query<&MazeStats, Changed<MazeStats>>,
+    mut text_query: Query<&mut Text, With<StatsText>>,
+) {
+    let Ok(stats) = stats_query.get_single() else {
+        return;
+    };
+
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+
+    text.sections[0].value = stats.to_string();
+}
+
+fn visibility(
+    overlay_query: Query<&Overlay>,
+    mut text_query: Query<&mut Visibility, With<StatsText>>,
+) {
+    let Ok(overlay) = overlay_query.get_single() else {
+        return;
+    };
+
+    let Ok(mut visibility) = text_query.get_single_mut() else {
+        return;
+    };
+
+    *visibility = if overlay.is_visible() {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+}
+
+/// Generate `num_seeds` mazes with every generator and print the average
+/// statistics, so that the generators can be compared.
+pub fn run_batch(num_seeds: u64) {
+    let start = IVec2::ZERO;
+    let goal = IVec2::new(GRID_WIDTH as i32 - 1, GRID_HEIGHT as i32 - 1);
+
+    println!(
+        "{:<14}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+        "Generator",
+        "Dead ends",
+        "Junction",
+        "Corridor",
+        "Turns",
+        "Solution",
+        "Ratio",
+        "Longest",
+        "River",
+        "Diameter"
+    );
+
+    for maze_type in MazeType::ALL {
+        let stats: Vec<_> = (0..num_seeds)
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let grid = maze::generate_grid(maze_type, GRID_WIDTH, GRID_HEIGHT, &mut rng);
+                MazeStats::new(&grid, start, goal)
+            })
+            .collect();
+
+        let mean =
+            |f: fn(&MazeStats) -> f32| stats.iter().map(f).sum::<f32>() / stats.len().max(1) as f32;
+
+        println!(
+            "{:<14}{:>10.2}{:>10.2}{:>10.2}{:>10.2}{:>10.2}{:>10.3}{:>10.2}{:>10.2}{:>10.2}",
+            format!("{:?}", maze_type),
+            mean(|s| s.dead_ends as f32),
+            mean(|s| s.junctions as f32),
+            mean(|s| s.corridors as f32),
+            mean(|s| s.turns as f32),
+            mean(|s| s.solution_length as f32),
+            mean(|s| s.solution_ratio),
+            mean(|s| s.longest_corridor as f32),
+            mean(|s| s.river),
+            mean(|s| s.diameter as f32),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A maze which snakes back and forth through every row
+    fn serpentine() -> Grid {
+        let mut grid = Grid::new();
+        for y in 0..GRID_HEIGHT as i32 {
+            for x in 0..GRID_WIDTH as i32 - 1 {
+                grid.remove_wall(IVec2::new(x, y), Dir::East).unwrap();
+            }
+            if y < GRID_HEIGHT as i32 - 1 {
+                let x = if y % 2 == 0 { GRID_WIDTH as i32 - 1 } else { 0 };
+                grid.remove_wall(IVec2::new(x, y), Dir::North).unwrap();
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_serpentine() {
+        let grid = serpentine();
+        let cells = grid.num_cells();
+        let stats = MazeStats::new(&grid, IVec2::ZERO, IVec2::new(0, GRID_HEIGHT as i32 - 1));
+
+        assert_eq!(stats.cells, cells);
+        assert_eq!(stats.dead_ends, 2);
+        assert_eq!(stats.junctions, 0);
+        assert_eq!(stats.turns, 2 * (GRID_HEIGHT - 1));
+        assert_eq!(
+            stats.dead_ends + stats.junctions + stats.corridors + stats.turns,
+            cells
+        );
+        assert_eq!(stats.solution_length, cells);
+        assert_eq!(stats.solution_ratio, 1.0);
+        assert_eq!(stats.longest_corridor, GRID_WIDTH);
+        assert_eq!(stats.river, cells as f32);
+        assert_eq!(stats.diameter, cells - 1);
+    }
+
+    #[test]
+    fn test_generated() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for maze_type in MazeType::ALL {
+            let grid = maze::generate_grid(maze_type, GRID_WIDTH, GRID_HEIGHT, &mut rng);
+            let stats = MazeStats::new(
+                &grid,
+                IVec2::ZERO,
+                IVec2::new(GRID_WIDTH as i32 - 1, GRID_HEIGHT as i32 - 1),
+            );
+
+            assert_eq!(
+                stats.dead_ends + stats.junctions + stats.corridors + stats.turns,
+                stats.cells
+            );
+            assert!(stats.solution_length >= GRID_WIDTH + GRID_HEIGHT - 1);
+            assert!(stats.diameter + 1 >= stats.solution_length);
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Range};
 
 use bevy::{
+    math::URect,
     prelude::*,
     render::{
         mesh::{Indices, PrimitiveTopology},
+        primitives::Aabb,
         render_asset::RenderAssetUsages,
         render_resource::{AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat},
         texture::ImageSampler,
@@ -11,12 +13,198 @@ use bevy::{
     sprite::{Material2d, Material2dPlugin, Mesh2dHandle},
 };
 
-use crate::consts::{GRID_HEIGHT, GRID_WIDTH};
+mod picking;
+
+pub use picking::{
+    register_picking, tile_to_world, world_to_tile, PickingSet, PointerWorld, TileClick, TileHover,
+    TilePointer,
+};
 
 #[derive(Component, Reflect)]
 pub struct Tilemap {
     pub grid_size: Vec2,
-    pub data: Vec<u8>,
+    data: Vec<Tile>,
+    #[reflect(ignore)]
+    dirty: DirtyRect,
+}
+
+/// The number of colours in a tilemap palette
+pub const PALETTE_SIZE: usize = 16;
+
+/// A tile index with flags saying how to draw it. Tiles are uploaded as two
+/// 16 bit channels, the index and the flags, and the flags are applied by
+/// `tilemap.wgsl`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct Tile {
+    pub index: u16,
+    pub flags: u16,
+}
+
+impl Tile {
+    /// Mirror the tile horizontally
+    pub const FLIP_X: u16 = 1 << 0;
+    /// Mirror the tile vertically
+    pub const FLIP_Y: u16 = 1 << 1;
+    /// Rotate the tile 90 degrees clockwise, before flipping it. Together
+    /// with the flips this gives every orientation.
+    pub const ROTATE: u16 = 1 << 2;
+    /// The palette colour the tile is tinted with is stored in bits 8 to 11
+    const PALETTE_SHIFT: u16 = 8;
+    const PALETTE_MASK: u16 = 0xf << Self::PALETTE_SHIFT;
+
+    pub const fn new(index: u16) -> Self {
+        Self { index, flags: 0 }
+    }
+
+    pub fn with_flags(self, flags: u16) -> Self {
+        Self {
+            flags: self.flags | flags,
+            ..self
+        }
+    }
+
+    /// Tint the tile with colour `palette` of the tilemap's `TilemapPalette`
+    pub fn with_palette(self, palette: u8) -> Self {
+        assert!(
+            (palette as usize) < PALETTE_SIZE,
+            "no palette colour {palette}"
+        );
+        Self {
+            flags: self.flags & !Self::PALETTE_MASK | (palette as u16) << Self::PALETTE_SHIFT,
+            ..self
+        }
+    }
+
+    pub fn palette(&self) -> u8 {
+        ((self.flags & Self::PALETTE_MASK) >> Self::PALETTE_SHIFT) as u8
+    }
+
+    /// The texel of the tile in an `Rg16Uint` texture
+    pub fn to_bytes(self) -> [u8; BYTES_PER_TILE] {
+        let [i0, i1] = self.index.to_le_bytes();
+        let [f0, f1] = self.flags.to_le_bytes();
+        [i0, i1, f0, f1]
+    }
+}
+
+impl From<u8> for Tile {
+    fn from(index: u8) -> Self {
+        Self::new(index as u16)
+    }
+}
+
+/// The size of a tile in the tilemap textures
+pub const BYTES_PER_TILE: usize = 4;
+
+/// The colours tiles are tinted with, see `Tile::with_palette`. Like the
+/// layout it is read when the chunks of the tilemap are built. Without a
+/// palette every colour is white, leaving the tiles as they are.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct TilemapPalette(pub [LinearRgba; PALETTE_SIZE]);
+
+impl Default for TilemapPalette {
+    fn default() -> Self {
+        Self([LinearRgba::WHITE; PALETTE_SIZE])
+    }
+}
+
+/// Where the tiles of a tilemap are drawn, in the local space of its entity.
+/// Tilemaps without a layout use the default one, with tiles one world unit
+/// wide starting at the origin. The layout is read when the chunks of the
+/// tilemap are built, later changes are only seen by picking.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct TilemapLayout {
+    /// The size of one tile in world units
+    pub tile_size: Vec2,
+    /// The bottom left corner of the first tile
+    pub origin: Vec2,
+}
+
+impl Default for TilemapLayout {
+    fn default() -> Self {
+        Self {
+            tile_size: Vec2::ONE,
+            origin: Vec2::ZERO,
+        }
+    }
+}
+
+impl TilemapLayout {
+    pub fn new(tile_size: Vec2, origin: Vec2) -> Self {
+        Self { tile_size, origin }
+    }
+
+    /// A layout putting the centre of a tilemap with `tiles` tiles at
+    /// `center`
+    pub fn centered(tiles: Vec2, tile_size: Vec2, center: Vec2) -> Self {
+        Self::new(tile_size, center - tiles * tile_size / 2.0)
+    }
+
+    /// The size of a tilemap with `tiles` tiles in world units
+    pub fn world_size(&self, tiles: Vec2) -> Vec2 {
+        tiles * self.tile_size
+    }
+
+    /// The area covered by the tiles in `rect`
+    pub fn world_rect(&self, rect: URect) -> Rect {
+        Rect::from_corners(
+            self.tile_to_local(rect.min.as_vec2()),
+            self.tile_to_local(rect.max.as_vec2()),
+        )
+    }
+
+    /// A position in tiles from the bottom left corner of the tilemap
+    pub fn local_to_tile(&self, local: Vec2) -> Vec2 {
+        (local - self.origin) / self.tile_size
+    }
+
+    pub fn tile_to_local(&self, tile: Vec2) -> Vec2 {
+        self.origin + tile * self.tile_size
+    }
+}
+
+/// The tiles of a tilemap changed since it was last uploaded, as the smallest
+/// rectangle containing all of them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect(Option<URect>);
+
+impl DirtyRect {
+    /// Every tile of a tilemap with `size` tiles
+    pub fn full(size: UVec2) -> Self {
+        Self(Some(URect::from_corners(UVec2::ZERO, size)))
+    }
+
+    pub fn add(&mut self, rect: URect) {
+        self.0 = Some(self.0.map_or(rect, |dirty| dirty.union(rect)));
+    }
+
+    pub fn add_tile(&mut self, pos: UVec2) {
+        self.add(URect::from_corners(pos, pos + 1));
+    }
+
+    pub fn get(&self) -> Option<URect> {
+        self.0
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = None;
+    }
+}
+
+/// The side length of the chunks a tilemap is split into, in tiles. Every
+/// chunk has its own texture and mesh, so only the chunks which changed are
+/// uploaded and chunks outside the view are culled. A changed chunk is
+/// uploaded as a whole, even if only a few of its tiles changed.
+pub const CHUNK_SIZE: u32 = 256;
+
+/// The chunk entities of a tilemap, which are its children
+#[derive(Component, Debug, Default)]
+pub struct TilemapChunks(pub Vec<Entity>);
+
+/// The part of the parent tilemap drawn by a chunk, in tiles
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TilemapChunk {
+    pub rect: URect,
 }
 
 #[derive(Component)]
@@ -26,18 +214,42 @@ pub struct Tileset {
 }
 
 pub trait TilemapData {
-    fn data(&self) -> &Vec<u8>;
+    /// The tiles are either `Tile`s or plain indices
+    type Item: Into<Tile> + Copy;
+
+    fn data(&self) -> &[Self::Item];
     fn size(&self) -> Vec4;
+
+    /// The tiles changed since `clear_dirty` was called, or `None` if there
+    /// are none. Only these tiles are compared and written, and only the
+    /// chunks they overlap are uploaded.
+    /// Without tracking every tile is dirty whenever the tilemap changes.
+    fn dirty(&self) -> Option<URect> {
+        DirtyRect::full(self.size().xy().as_uvec2()).get()
+    }
+
+    /// Called after the dirty tiles were uploaded
+    fn clear_dirty(&mut self) {}
 }
 
 impl TilemapData for Tilemap {
-    fn data(&self) -> &Vec<u8> {
+    type Item = Tile;
+
+    fn data(&self) -> &[Tile] {
         &self.data
     }
 
     fn size(&self) -> Vec4 {
         Vec4::new(self.grid_size.x, self.grid_size.y, 0.0, 0.0)
     }
+
+    fn dirty(&self) -> Option<URect> {
+        self.dirty.get()
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
 }
 
 pub trait TilemapMaterialShader: TypePath + Clone + Send + Sync {
@@ -61,13 +273,19 @@ struct TilemapMaterial<T: TilemapMaterialShader> {
     tileset_texture: Handle<Image>,
     #[texture(3, sample_type = "u_int")]
     tilemap_texture: Handle<Image>,
+    #[uniform(4)]
+    palette: [Vec4; PALETTE_SIZE],
     _phantom: PhantomData<T>,
 }
 
 pub fn plugin(app: &mut App) {
-    app.register_type::<Tilemap>();
+    app.register_type::<Tilemap>()
+        .register_type::<TilemapLayout>()
+        .register_type::<TilemapPalette>()
+        .add_plugins(picking::plugin);
     register_shader::<TilemapShader>(app);
     register_data::<TilemapShader, Tilemap>(app);
+    register_picking::<Tilemap>(app);
 }
 
 pub fn register_shader<S: TilemapMaterialShader>(app: &mut App) {
@@ -86,76 +304,280 @@ impl Tilemap {
     pub fn new(width: u32, height: u32) -> Self {
         Self {
             grid_size: Vec2::new(width as f32, height as f32),
-            data: vec![0; (width * height) as usize],
+            data: vec![Tile::default(); (width * height) as usize],
+            dirty: DirtyRect::full(UVec2::new(width, height)),
         }
     }
+
+    fn index(&self, pos: UVec2) -> usize {
+        pos.y as usize * self.grid_size.x as usize + pos.x as usize
+    }
+
+    pub fn get(&self, pos: UVec2) -> Tile {
+        self.data[self.index(pos)]
+    }
+
+    /// Set a tile, marking it dirty if it changed
+    pub fn set(&mut self, pos: UVec2, tile: impl Into<Tile>) {
+        let tile = tile.into();
+        let index = self.index(pos);
+        if self.data[index] != tile {
+            self.data[index] = tile;
+            self.dirty.add_tile(pos);
+        }
+    }
+
+    /// Replace every tile with the ones in `data`, marking the tiles which
+    /// changed dirty
+    pub fn copy_from(&mut self, data: &[Tile]) {
+        let width = self.grid_size.x as usize;
+        for (index, (tile, new)) in self.data.iter_mut().zip(data).enumerate() {
+            if *tile != *new {
+                *tile = *new;
+                self.dirty
+                    .add_tile(UVec2::new((index % width) as u32, (index / width) as u32));
+            }
+        }
+    }
+
+    pub fn fill(&mut self, tile: impl Into<Tile>) {
+        let tile = tile.into();
+        if self.data.iter().any(|t| *t != tile) {
+            self.data.fill(tile);
+            self.dirty = DirtyRect::full(self.grid_size.as_uvec2());
+        }
+    }
+}
+
+/// Split a tilemap of `size` tiles into chunks of at most `CHUNK_SIZE` tiles,
+/// row by row from the bottom left
+pub fn chunk_rects(size: UVec2) -> Vec<URect> {
+    let mut rects = Vec::new();
+    for y in (0..size.y).step_by(CHUNK_SIZE as usize) {
+        for x in (0..size.x).step_by(CHUNK_SIZE as usize) {
+            let min = UVec2::new(x, y);
+            rects.push(URect::from_corners(min, (min + CHUNK_SIZE).min(size)));
+        }
+    }
+    rects
+}
+
+/// The rows of `data`, a tilemap `width` tiles wide, covered by `rect`
+fn chunk_rows<D>(data: &[D], width: usize, rect: URect) -> impl Iterator<Item = &[D]> {
+    let (x0, x1) = (rect.min.x as usize, rect.max.x as usize);
+    (rect.min.y as usize..rect.max.y as usize).map(move |y| &data[y * width + x0..y * width + x1])
+}
+
+/// The byte ranges of the rows of `rect` in the texture data of the chunk
+/// covering `chunk_rect`, which contains `rect`
+fn texel_rows(chunk_rect: URect, rect: URect) -> impl Iterator<Item = Range<usize>> {
+    let chunk_width = chunk_rect.width() as usize;
+    let offset = rect.min - chunk_rect.min;
+    let (x, y) = (offset.x as usize, offset.y as usize);
+    let len = rect.width() as usize * BYTES_PER_TILE;
+    (y..y + rect.height() as usize).map(move |y| {
+        let start = (y * chunk_width + x) * BYTES_PER_TILE;
+        start..start + len
+    })
+}
+
+/// Whether `chunk`, the texture data of the chunk covering `chunk_rect`,
+/// differs from the tiles of `rect` in `data`, a tilemap `width` tiles wide.
+/// Only the part of the chunk covered by `rect` is compared.
+pub fn tiles_differ<D: Into<Tile> + Copy>(
+    data: &[D],
+    width: usize,
+    rect: URect,
+    chunk_rect: URect,
+    chunk: &[u8],
+) -> bool {
+    chunk_rows(data, width, rect)
+        .zip(texel_rows(chunk_rect, rect))
+        .any(|(tiles, range)| {
+            tiles
+                .iter()
+                .zip(chunk[range].chunks_exact(BYTES_PER_TILE))
+                .any(|(tile, texel)| (*tile).into().to_bytes() != texel)
+        })
+}
+
+/// Copy the tiles of `rect` in `data`, a tilemap `width` tiles wide, into the
+/// part they cover of `chunk`, the texture data of the chunk covering
+/// `chunk_rect`
+pub fn copy_tiles<D: Into<Tile> + Copy>(
+    data: &[D],
+    width: usize,
+    rect: URect,
+    chunk_rect: URect,
+    chunk: &mut [u8],
+) {
+    for (tiles, range) in chunk_rows(data, width, rect).zip(texel_rows(chunk_rect, rect)) {
+        for (tile, texel) in tiles
+            .iter()
+            .zip(chunk[range].chunks_exact_mut(BYTES_PER_TILE))
+        {
+            texel.copy_from_slice(&(*tile).into().to_bytes());
+        }
+    }
+}
+
+/// Whether `chunk`, the texture data of a chunk, differs from the tiles of
+/// `rect` in `data`, a tilemap `width` tiles wide
+pub fn chunk_differs<D: Into<Tile> + Copy>(
+    data: &[D],
+    width: usize,
+    rect: URect,
+    chunk: &[u8],
+) -> bool {
+    tiles_differ(data, width, rect, rect, chunk)
+}
+
+/// Copy the tiles of `rect` in `data`, a tilemap `width` tiles wide, into
+/// `chunk`, the texture data of a chunk
+pub fn copy_chunk<D: Into<Tile> + Copy>(data: &[D], width: usize, rect: URect, chunk: &mut [u8]) {
+    copy_tiles(data, width, rect, rect, chunk);
+}
+
+/// The uniform of a chunk. The tilemap size is replaced by the chunk size and
+/// the other components, like the number of overlay hexes, are scaled with it.
+fn chunk_size_uniform(size: Vec4, rect: URect) -> Vec4 {
+    let chunk_size = rect.size().as_vec2();
+    let fraction = chunk_size / size.xy();
+    Vec4::new(
+        chunk_size.x,
+        chunk_size.y,
+        size.z * fraction.x,
+        size.w * fraction.y,
+    )
+}
+
+/// The part of a chunk covering `rect` which has to be compared with the
+/// tilemap, if any
+fn dirty_part(rect: URect, dirty: Option<URect>) -> Option<URect> {
+    dirty
+        .map(|dirty| rect.intersect(dirty))
+        .filter(|part| !part.is_empty())
 }
 
 fn update_tilemaps<S: TilemapMaterialShader, T: TilemapData + Component>(
-    query: Query<(&T, &Handle<TilemapMaterial<S>>), Changed<T>>,
+    mut query: Query<(&mut T, &TilemapChunks), Changed<T>>,
+    chunk_query: Query<(&TilemapChunk, &Handle<TilemapMaterial<S>>)>,
     mut materials: ResMut<Assets<TilemapMaterial<S>>>,
     mut images: ResMut<Assets<Image>>,
 ) {
-    for (tilemap, material) in query.iter() {
-        // This needs to be get mut to signal that the material has changed
-        let Some(material) = materials.get_mut(material) else {
-            continue;
-        };
-        let Some(image) = images.get_mut(&material.tilemap_texture) else {
-            continue;
-        };
-        image.data.clone_from(tilemap.data());
+    for (mut tilemap, chunks) in query.iter_mut() {
+        let width = tilemap.size().x as usize;
+        let dirty = tilemap.dirty();
+        for (chunk, material) in chunk_query.iter_many(&chunks.0) {
+            let Some(part) = dirty_part(chunk.rect, dirty) else {
+                continue;
+            };
+            let Some(texture) = materials
+                .get(material)
+                .map(|material| material.tilemap_texture.clone())
+            else {
+                continue;
+            };
+            let dirty = images.get(&texture).is_some_and(|image| {
+                tiles_differ(tilemap.data(), width, part, chunk.rect, &image.data)
+            });
+            if !dirty {
+                continue;
+            }
+
+            // Only the dirty part is written, but Bevy uploads the whole
+            // texture of a changed image, so uploads are still per chunk.
+            // This needs to be get mut to signal that the material has changed
+            materials.get_mut(material);
+            if let Some(image) = images.get_mut(&texture) {
+                copy_tiles(tilemap.data(), width, part, chunk.rect, &mut image.data);
+            }
+        }
+        tilemap.bypass_change_detection().clear_dirty();
     }
 }
 
 #[allow(clippy::type_complexity)]
 fn construct_materials<S: TilemapMaterialShader, T: TilemapData + Component>(
     mut commands: Commands,
-    query: Query<(Entity, &T, &Tileset), Without<Handle<TilemapMaterial<S>>>>,
+    query: Query<
+        (
+            Entity,
+            &T,
+            &Tileset,
+            Option<&TilemapLayout>,
+            Option<&TilemapPalette>,
+        ),
+        Without<TilemapChunks>,
+    >,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<TilemapMaterial<S>>>,
     mut images: ResMut<Assets<Image>>,
 ) {
-    for (entity, tilemap, tileset) in query.iter() {
-        let mut tilemap_image = Image::new(
-            Extent3d {
-                width: tilemap.size().x as u32,
-                height: tilemap.size().y as u32,
-                depth_or_array_layers: 1,
-            },
-            TextureDimension::D2,
-            tilemap.data().clone(),
-            TextureFormat::R8Uint,
-            RenderAssetUsages::all(),
-        );
-        tilemap_image.sampler = ImageSampler::nearest();
-
-        let tilemap_handle = images.add(tilemap_image);
-
+    for (entity, tilemap, tileset, layout, palette) in query.iter() {
         let Some(tileset_image) = images.get_mut(&tileset.image) else {
             continue;
         };
         tileset_image.reinterpret_stacked_2d_as_array(tileset.num_tiles);
         tileset_image.sampler = ImageSampler::nearest();
 
-        let material = materials.add(TilemapMaterial::<S> {
-            grid_size: tilemap.size(),
-            tileset_texture: tileset.image.clone(),
-            tilemap_texture: tilemap_handle,
-            _phantom: PhantomData,
-        });
+        let size = tilemap.size();
+        let tiles = size.xy().as_uvec2();
+        let layout = layout.copied().unwrap_or_default();
+        let palette = palette
+            .copied()
+            .unwrap_or_default()
+            .0
+            .map(|color| color.to_vec4());
+
+        let chunks = chunk_rects(tiles)
+            .into_iter()
+            .map(|rect| {
+                let mut data =
+                    vec![0; rect.width() as usize * rect.height() as usize * BYTES_PER_TILE];
+                copy_chunk(tilemap.data(), tiles.x as usize, rect, &mut data);
+                let mut tilemap_image = Image::new(
+                    Extent3d {
+                        width: rect.width(),
+                        height: rect.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rg16Uint,
+                    RenderAssetUsages::all(),
+                );
+                tilemap_image.sampler = ImageSampler::nearest();
+
+                let material = materials.add(TilemapMaterial::<S> {
+                    grid_size: chunk_size_uniform(size, rect),
+                    tileset_texture: tileset.image.clone(),
+                    tilemap_texture: images.add(tilemap_image),
+                    palette,
+                    _phantom: PhantomData,
+                });
+
+                let world_rect = layout.world_rect(rect);
+                let mesh: Mesh2dHandle = meshes.add(create_mesh_rect(world_rect)).into();
 
-        let mesh_handle = meshes.add(create_mesh(UVec2::new(
-            GRID_WIDTH as u32,
-            GRID_HEIGHT as u32,
-        )));
-        let mesh: Mesh2dHandle = mesh_handle.into();
+                commands
+                    .spawn((
+                        mesh,
+                        material,
+                        TilemapChunk { rect },
+                        Aabb::from_min_max(world_rect.min.extend(0.0), world_rect.max.extend(0.0)),
+                        SpatialBundle::default(),
+                        Name::from(format!("Chunk {},{}", rect.min.x, rect.min.y)),
+                    ))
+                    .set_parent(entity)
+                    .id()
+            })
+            .collect();
 
         commands
             .entity(entity)
             .insert((
-                mesh,
-                material,
+                TilemapChunks(chunks),
                 GlobalTransform::default(),
                 Visibility::default(),
                 InheritedVisibility::default(),
@@ -174,18 +596,189 @@ impl<T: TilemapMaterialShader + Clone + Send + Sync> Material2d for TilemapMater
 }
 
 pub fn create_mesh(size: UVec2) -> Mesh {
-    let x: f32 = size.x as f32;
-    let y = size.y as f32;
+    create_mesh_rect(Rect::from_corners(Vec2::ZERO, size.as_vec2()))
+}
+
+/// A quad covering `rect`, with UVs from 0 to 1
+pub fn create_mesh_rect(rect: Rect) -> Mesh {
+    let Rect { min, max } = rect;
     Mesh::new(
         PrimitiveTopology::TriangleList,
         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
     )
     .with_inserted_attribute(
         Mesh::ATTRIBUTE_POSITION,
-        vec![[0.0, 0.0, 0.0], [0.0, y, 0.0], [x, y, 0.0], [x, 0.0, 0.0]],
+        vec![
+            [min.x, min.y, 0.0],
+            [min.x, max.y, 0.0],
+            [max.x, max.y, 0.0],
+            [max.x, min.y, 0.0],
+        ],
     )
     .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, {
         vec![[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]]
     })
     .with_inserted_indices(Indices::U32(vec![0, 1, 2, 2, 3, 0]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_rects() {
+        assert_eq!(chunk_rects(UVec2::new(8, 8)), [URect::new(0, 0, 8, 8)]);
+
+        let size = UVec2::new(2 * CHUNK_SIZE + 3, CHUNK_SIZE);
+        let rects = chunk_rects(size);
+        assert_eq!(rects.len(), 3);
+        assert_eq!(
+            rects[2],
+            URect::new(2 * CHUNK_SIZE, 0, 2 * CHUNK_SIZE + 3, CHUNK_SIZE)
+        );
+        let area: u32 = rects.iter().map(|rect| rect.width() * rect.height()).sum();
+        assert_eq!(area, size.x * size.y);
+    }
+
+    #[test]
+    fn test_copy_chunk() {
+        let data: Vec<u8> = (0..20).collect();
+        let rect = URect::new(1, 2, 4, 4);
+
+        let mut chunk = vec![0; 6 * BYTES_PER_TILE];
+        assert!(chunk_differs(&data, 5, rect, &chunk));
+        copy_chunk(&data, 5, rect, &mut chunk);
+        let indices: Vec<u8> = chunk.chunks_exact(BYTES_PER_TILE).map(|t| t[0]).collect();
+        assert_eq!(indices, [11, 12, 13, 16, 17, 18]);
+        assert!(!chunk_differs(&data, 5, rect, &chunk));
+
+        // Changes outside the chunk do not make it dirty
+        let mut data = data;
+        data[0] = 100;
+        assert!(!chunk_differs(&data, 5, rect, &chunk));
+        data[17] = 100;
+        assert!(chunk_differs(&data, 5, rect, &chunk));
+
+        // Only the given part of the chunk is compared and written
+        let part = URect::new(2, 3, 4, 4);
+        assert!(!tiles_differ(
+            &data,
+            5,
+            URect::new(1, 2, 4, 3),
+            rect,
+            &chunk
+        ));
+        assert!(tiles_differ(&data, 5, part, rect, &chunk));
+        copy_tiles(&data, 5, part, rect, &mut chunk);
+        let indices: Vec<u8> = chunk.chunks_exact(BYTES_PER_TILE).map(|t| t[0]).collect();
+        assert_eq!(indices, [11, 12, 13, 16, 100, 18]);
+        assert!(!chunk_differs(&data, 5, rect, &chunk));
+    }
+
+    #[test]
+    fn test_tile() {
+        let tile = Tile::new(0x1234)
+            .with_flags(Tile::FLIP_X | Tile::ROTATE)
+            .with_palette(3);
+        assert_eq!(tile.palette(), 3);
+        assert_eq!(tile.to_bytes(), [0x34, 0x12, 0x05, 0x03]);
+        assert_eq!(tile.with_palette(15).palette(), 15);
+        assert_eq!(tile.with_palette(0).flags, Tile::FLIP_X | Tile::ROTATE);
+        assert_eq!(Tile::from(7), Tile::new(7));
+
+        // Flags are uploaded too, so changing only them makes a chunk dirty
+        let rect = URect::new(0, 0, 1, 1);
+        let mut chunk = vec![0; BYTES_PER_TILE];
+        copy_chunk(&[Tile::new(1)], 1, rect, &mut chunk);
+        assert!(chunk_differs(
+            &[tile.with_flags(Tile::FLIP_Y)],
+            1,
+            rect,
+            &chunk
+        ));
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = TilemapLayout::default();
+        assert_eq!(layout.world_size(Vec2::new(8.0, 4.0)), Vec2::new(8.0, 4.0));
+        assert_eq!(
+            layout.world_rect(URect::new(2, 0, 4, 3)),
+            Rect::new(2.0, 0.0, 4.0, 3.0)
+        );
+
+        // A background of 33 tiles a quarter unit wide around the maze
+        let layout =
+            TilemapLayout::centered(Vec2::splat(33.0), Vec2::splat(0.25), Vec2::splat(4.0));
+        assert_eq!(layout.origin, Vec2::splat(-0.125));
+        assert_eq!(layout.world_size(Vec2::splat(33.0)), Vec2::splat(8.25));
+        assert_eq!(
+            layout.world_rect(URect::new(0, 0, 33, 33)),
+            Rect::new(-0.125, -0.125, 8.125, 8.125)
+        );
+        assert_eq!(
+            layout.local_to_tile(Vec2::new(0.0, 1.0)),
+            Vec2::new(0.5, 4.5)
+        );
+        assert_eq!(
+            layout.tile_to_local(Vec2::new(0.5, 4.5)),
+            Vec2::new(0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_dirty_rect() {
+        let mut tilemap = Tilemap::new(600, 300);
+        assert_eq!(tilemap.dirty(), Some(URect::new(0, 0, 600, 300)));
+        tilemap.clear_dirty();
+        assert_eq!(tilemap.dirty(), None);
+
+        // Setting a tile to its current value changes nothing
+        tilemap.set(UVec2::new(3, 4), Tile::new(0));
+        tilemap.fill(Tile::new(0));
+        assert_eq!(tilemap.dirty(), None);
+
+        tilemap.set(UVec2::new(3, 4), Tile::new(1));
+        tilemap.set(UVec2::new(300, 2), Tile::new(1).with_flags(Tile::FLIP_X));
+        assert_eq!(tilemap.dirty(), Some(URect::new(3, 2, 301, 5)));
+        assert_eq!(tilemap.get(UVec2::new(300, 2)).flags, Tile::FLIP_X);
+
+        let rects = chunk_rects(UVec2::new(600, 300));
+        let parts: Vec<_> = rects
+            .iter()
+            .map(|rect| dirty_part(*rect, tilemap.dirty()))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                Some(URect::new(3, 2, 256, 5)),
+                Some(URect::new(256, 2, 301, 5)),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+
+        tilemap.clear_dirty();
+        let mut data = tilemap.data().to_vec();
+        data[599] = Tile::new(2);
+        tilemap.copy_from(&data);
+        assert_eq!(tilemap.dirty(), Some(URect::new(599, 0, 600, 1)));
+        assert_eq!(dirty_part(rects[0], tilemap.dirty()), None);
+    }
+
+    #[test]
+    fn test_chunk_size_uniform() {
+        let size = Vec4::new(512.0, 256.0, 64.0, 32.0);
+        let rect = URect::new(256, 0, 512, 256);
+        assert_eq!(
+            chunk_size_uniform(size, rect),
+            Vec4::new(256.0, 256.0, 32.0, 32.0)
+        );
+        assert_eq!(
+            chunk_size_uniform(Vec4::new(8.0, 8.0, 0.0, 0.0), URect::new(0, 0, 8, 8)),
+            Vec4::new(8.0, 8.0, 0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Finding the tile under the mouse. Tilemaps with a `TilePointer` component
+//! track the mouse and send `TileHover` and `TileClick` events.
+
+use bevy::{input::InputSystem, prelude::*, window::PrimaryWindow};
+
+use super::{TilemapData, TilemapLayout};
+
+/// The mouse position in world coordinates, or `None` if it is outside the
+/// window
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct PointerWorld(pub Option<Vec2>);
+
+/// Add this to a tilemap to track the mouse over it
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct TilePointer {
+    /// The mouse position in tiles from the bottom left corner of the
+    /// tilemap, or `None` if the mouse is not over it
+    pub position: Option<Vec2>,
+}
+
+impl TilePointer {
+    /// The tile under the mouse
+    pub fn tile(&self) -> Option<IVec2> {
+        self.position.map(|position| position.floor().as_ivec2())
+    }
+}
+
+/// The mouse moved onto another tile of `entity`, or off the tilemap
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct TileHover {
+    pub entity: Entity,
+    pub tile: Option<IVec2>,
+}
+
+/// A mouse button was pressed over a tile of `entity`
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct TileClick {
+    pub entity: Entity,
+    pub button: MouseButton,
+    pub tile: IVec2,
+    /// The exact position in tiles, see `TilePointer::position`
+    pub position: Vec2,
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PickingSet;
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<PointerWorld>()
+        .add_event::<TileHover>()
+        .add_event::<TileClick>()
+        .configure_sets(PreUpdate, PickingSet.after(InputSystem))
+        .add_systems(PreUpdate, update_pointer_world.in_set(PickingSet));
+}
+
+/// Track the mouse over tilemaps of type `T`
+pub fn register_picking<T: TilemapData + Component>(app: &mut App) {
+    app.add_systems(
+        PreUpdate,
+        pick_tiles::<T>
+            .in_set(PickingSet)
+            .after(update_pointer_world),
+    );
+}
+
+/// The position of `world` in tiles from the bottom left corner of a tilemap
+/// with `size` tiles, if it is over the tilemap
+pub fn world_to_tile(
+    transform: &GlobalTransform,
+    layout: &TilemapLayout,
+    size: Vec2,
+    world: Vec2,
+) -> Option<Vec2> {
+    let local = transform
+        .affine()
+        .inverse()
+        .transform_point3(world.extend(0.0))
+        .xy();
+    let position = layout.local_to_tile(local);
+    (position.cmpge(Vec2::ZERO).all() && position.cmplt(size).all()).then_some(position)
+}
+
+/// The world position of the centre of `tile`
+pub fn tile_to_world(transform: &GlobalTransform, layout: &TilemapLayout, tile: IVec2) -> Vec2 {
+    let local = layout.tile_to_local(tile.as_vec2() + 0.5);
+    transform.transform_point(local.extend(0.0)).xy()
+}
+
+/// Without a window, for example in tests, the position is left as it is
+fn update_pointer_world(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut pointer: ResMut<PointerWorld>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let world = window.cursor_position().and_then(|cursor| {
+        let (camera, camera_transform) = camera_query.get_single().ok()?;
+        camera.viewport_to_world_2d(camera_transform, cursor)
+    });
+    pointer.set_if_neq(PointerWorld(world));
+}
+
+fn pick_tiles<T: TilemapData + Component>(
+    mut query: Query<(
+        Entity,
+        &T,
+        &GlobalTransform,
+        Option<&TilemapLayout>,
+        &mut TilePointer,
+    )>,
+    pointer: Res<PointerWorld>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut hover_events: EventWriter<TileHover>,
+    mut click_events: EventWriter<TileClick>,
+) {
+    for (entity, tilemap, transform, layout, mut tile_pointer) in query.iter_mut() {
+        let size = tilemap.size().xy();
+        let layout = layout.copied().unwrap_or_default();
+        let position = pointer
+            .0
+            .and_then(|world| world_to_tile(transform, &layout, size, world));
+
+        let previous = tile_pointer.tile();
+        tile_pointer.set_if_neq(TilePointer { position });
+        if tile_pointer.tile() != previous {
+            hover_events.send(TileHover {
+                entity,
+                tile: tile_pointer.tile(),
+            });
+        }
+
+        let (Some(tile), Some(position)) = (tile_pointer.tile(), position) else {
+            continue;
+        };
+        for button in mouse.get_just_pressed() {
+            click_events.send(TileClick {
+                entity,
+                button: *button,
+                tile,
+                position,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tilemap::Tilemap;
+
+    use super::*;
+
+    #[test]
+    fn test_world_to_tile() {
+        let size = Vec2::new(8.0, 8.0);
+        let layout = TilemapLayout::default();
+        let transform = GlobalTransform::from_translation(Vec3::new(1.0, 2.0, 5.0));
+        assert_eq!(
+            world_to_tile(&transform, &layout, size, Vec2::new(1.5, 2.25)),
+            Some(Vec2::new(0.5, 0.25))
+        );
+        assert_eq!(
+            world_to_tile(&transform, &layout, size, Vec2::new(0.5, 2.5)),
+            None
+        );
+        assert_eq!(world_to_tile(&transform, &layout, size, size + 1.5), None);
+
+        // Tiles half a unit wide starting left of the entity
+        let layout = TilemapLayout::new(Vec2::splat(0.5), Vec2::new(-1.0, 0.0));
+        let transform = GlobalTransform::IDENTITY;
+        let position =
+            world_to_tile(&transform, &layout, 2.0 * size, Vec2::new(0.25, 0.75)).unwrap();
+        assert_eq!(position, Vec2::new(2.5, 1.5));
+        assert_eq!(
+            tile_to_world(&transform, &layout, position.as_ivec2()),
+            Vec2::new(0.25, 0.75)
+        );
+        assert_eq!(
+            world_to_tile(&transform, &layout, 2.0 * size, Vec2::new(-1.25, 0.75)),
+            None
+        );
+
+        let layout = TilemapLayout::default();
+        let transform = GlobalTransform::from_scale(Vec3::new(2.0, 0.5, 1.0));
+        assert_eq!(
+            world_to_tile(&transform, &layout, size, Vec2::new(3.0, 1.0)),
+            Some(Vec2::new(1.5, 2.0))
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<ButtonInput<MouseButton>>()
+            .add_plugins((plugin, register_picking::<Tilemap>));
+        let entity = app
+            .world_mut()
+            .spawn((
+                Tilemap::new(4, 4),
+                TilemapLayout::new(Vec2::splat(2.0), Vec2::ZERO),
+                GlobalTransform::IDENTITY,
+                TilePointer::default(),
+            ))
+            .id();
+
+        let scale = Vec2::splat(2.0);
+        let move_to = |app: &mut App, world: Option<Vec2>| {
+            app.insert_resource(PointerWorld(world));
+            app.update();
+            let events = app.world().resource::<Events<TileHover>>();
+            events
+                .get_reader()
+                .read(events)
+                .map(|event| event.tile)
+                .collect::<Vec<_>>()
+        };
+
+        let tile = IVec2::new(1, 2);
+        let over_tile = Some((tile.as_vec2() + 0.5) * scale);
+        assert_eq!(move_to(&mut app, over_tile), [Some(tile)]);
+        let pointer = app.world().get::<TilePointer>(entity).unwrap();
+        assert_eq!(pointer.tile(), Some(tile));
+
+        app.world_mut()
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .press(MouseButton::Left);
+        move_to(&mut app, over_tile);
+        let events = app.world().resource::<Events<TileClick>>();
+        let clicks: Vec<_> = events.get_reader().read(events).copied().collect();
+        assert_eq!(clicks.len(), 1);
+        assert_eq!((clicks[0].entity, clicks[0].tile), (entity, tile));
+
+        assert!(move_to(&mut app, None).contains(&None));
+        assert_eq!(app.world().get::<TilePointer>(entity).unwrap().tile(), None);
+    }
+}
@@ -5,7 +5,6 @@ use bevy::{
         render_resource::{Extent3d, TextureDimension},
         texture::ImageSampler,
     },
-    sprite::Mesh2dHandle,
 };
 
 use crate::consts::*;
@@ -128,9 +127,16 @@ pub fn expand(image: Image) -> Image {
     tileset_image
 }
 
+#[allow(clippy::type_complexity)]
 pub fn construct_tileset(
     mut commands: Commands,
-    query: Query<(Entity, &Tileset), (Without<crate::tilemap::Tileset>, Without<Mesh2dHandle>)>,
+    query: Query<
+        (Entity, &Tileset),
+        (
+            Without<crate::tilemap::Tileset>,
+            Without<crate::tilemap::TilemapChunks>,
+        ),
+    >,
     mut images: ResMut<Assets<Image>>,
 ) {
     for (entity, loader) in query.iter() {